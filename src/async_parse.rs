@@ -0,0 +1,122 @@
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+
+use crate::parser::{Builder, Parser};
+use crate::secret::LineBuffer;
+use crate::{Encoding, Netrc, ParseOptions, Result};
+
+/// Read a line like the blocking `read_line` of the crate root.
+async fn read_line<A: AsyncBufRead + Unpin>(buf: &mut A,
+                                            line: &mut String,
+                                            max: usize,
+                                            encoding: Encoding) -> std::io::Result<usize> {
+    let mut buf = buf.take((max as u64).saturating_add(1));
+    if encoding == Encoding::Utf8 {
+        return buf.read_line(line).await;
+    }
+    let mut bytes = Vec::new();
+    let len = buf.read_until(b'\n', &mut bytes).await?;
+    encoding.decode(&bytes, line);
+    #[cfg(feature = "secure")]
+    crate::secret::wipe(&mut bytes);
+    Ok(len)
+}
+
+impl Netrc {
+    /// Parse a `Netrc` object from an async byte stream.
+    ///
+    /// This is the async counterpart of `Netrc::parse` and is only
+    /// available with the `tokio` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap()
+    /// #     .block_on(async {
+    /// let input: &[u8] = b"machine example.com login foo password bar";
+    /// let netrc = Netrc::parse_async(input).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn parse_async<A: AsyncRead + Unpin>(buf: A) -> Result<Netrc> {
+        Netrc::parse_async_with_options(BufReader::new(buf), &Default::default()).await
+    }
+
+    /// Parse a `Netrc` object from a buffered async byte stream with the
+    /// given options, sharing the parser of `Netrc::parse_with_options`.
+    ///
+    /// Only available with the `tokio` feature.
+    pub async fn parse_async_with_options<A>(mut buf: A,
+                                             options: &ParseOptions) -> Result<Netrc>
+        where A: AsyncBufRead + Unpin
+    {
+        let mut parser = Parser::new(options, Builder::new());
+        let max = options.max_line_length;
+        let mut line = LineBuffer::for_lines(max, options.encoding);
+        while read_line(&mut buf, &mut line, max, options.encoding).await? > 0 {
+            parser.feed_line(&line)?;
+            line.clear();
+        }
+        parser.finish_netrc()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Encoding, Error, Netrc, ParseOptions};
+
+    #[tokio::test]
+    async fn parse_async_simple() {
+        let input: &[u8] = b"machine example.com
+                             login test
+                             password p@ssw0rd
+                             macdef init
+                                    bin
+
+                             default login def";
+        let netrc = Netrc::parse_async(input).await.unwrap();
+        assert_eq!(netrc.hosts.len(), 1);
+        let (ref name, ref mach) = netrc.hosts[0];
+        assert_eq!(name, "example.com");
+        assert_eq!(mach.login, "test");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "p@ssw0rd");
+        assert_eq!(netrc.macros[0].name, "init");
+        assert_eq!(netrc.default.unwrap().login, "def");
+    }
+
+    #[tokio::test]
+    async fn parse_async_error() {
+        let input: &[u8] = b"machine foo.com login bar port quux";
+        match Netrc::parse_async(input).await.unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Unable to parse port number `quux'");
+                assert_eq!(line, 1);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn parse_async_with_options() {
+        let input: &[u8] = b"machine a.com login \"Andr\xe9 B\" port 21\n";
+        let options = ParseOptions {
+            quoted_values: true,
+            encoding: Encoding::Latin1,
+            ..Default::default()
+        };
+        let netrc = Netrc::parse_async_with_options(input, &options).await.unwrap();
+        assert_eq!(netrc.hosts[0].1.login, "Andr\u{e9} B");
+        assert!(Netrc::parse_async(input).await.is_err());
+
+        let options = ParseOptions { max_line_length: 8, ..Default::default() };
+        match Netrc::parse_async_with_options(input, &options).await.unwrap_err() {
+            Error::Parse { msg, .. } =>
+                assert_eq!(msg, "Line exceeds the maximum length of 8 bytes"),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        let options = ParseOptions { max_line_length: usize::MAX, ..Default::default() };
+        let input: &[u8] = b"machine a.com login abcdefghijklmnopqrstuvwxyz\n";
+        let netrc = Netrc::parse_async_with_options(input, &options).await.unwrap();
+        assert_eq!(netrc.hosts[0].1.login, "abcdefghijklmnopqrstuvwxyz");
+    }
+}
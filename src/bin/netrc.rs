@@ -0,0 +1,314 @@
+//! Reads and edits the user's netrc file from the shell, in the spirit of
+//! git's credential helpers.  See `USAGE`.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use netrc::document::Document;
+use netrc::{Machine, NetrcFormat};
+
+const USAGE: &str = "\
+Usage: netrc [--file PATH] COMMAND
+
+Commands:
+  get [--json] HOST       Print the login and password for HOST, one per
+                          line, falling back to the `default' entry
+  set HOST [--login LOGIN] [--password-stdin]
+                          Set fields of the entry for HOST, adding it if
+                          missing; the password is the first line of input
+  delete HOST             Remove the entry for HOST
+  list                    Print the host of every entry, one per line
+  fmt [--check]           Lay out the file as `NetrcFormat::canonical'
+                          does, dropping comments; with --check, only
+                          fail if it is not laid out so
+
+The file is PATH, else found as by curl: $NETRC, else ~/.netrc.";
+
+/// Why a command failed: bad arguments, with the usage printed, or any
+/// other error.
+#[derive(Debug)]
+enum Failure {
+    Usage(String),
+    Other(String),
+}
+
+impl<E: std::fmt::Display> From<E> for Failure {
+    fn from(e: E) -> Failure {
+        Failure::Other(e.to_string())
+    }
+}
+
+fn usage<T>(msg: &str) -> Result<T, Failure> {
+    Err(Failure::Usage(msg.to_string()))
+}
+
+/// The next argument, which `flag` takes as its value.
+fn value_of(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, Failure> {
+    match args.next() {
+        Some(value) => Ok(value),
+        None        => usage(&format!("Missing value for `{}'", flag)),
+    }
+}
+
+fn read_document(path: &Path) -> Result<Document, Failure> {
+    match fs::read_to_string(path) {
+        Ok(text)                                      => Ok(Document::parse(&text)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Document::default()),
+        Err(e)                                        => Err(e.into()),
+    }
+}
+
+fn get(path: &Path,
+       args: impl Iterator<Item = String>,
+       out: &mut dyn Write) -> Result<(), Failure> {
+    let mut json = false;
+    let mut host = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json"                  => json = true,
+            _ if arg.starts_with('-') => return usage(&format!("Unknown option `{}'", arg)),
+            _ if host.is_none()       => host = Some(arg),
+            _                         => return usage("Too many arguments"),
+        }
+    }
+    let host = match host {
+        Some(host) => host,
+        None       => return usage("Missing host"),
+    };
+    let netrc = read_document(path)?.to_netrc()?;
+    let mach = netrc.get_required(&host)?;
+    let password = mach.password.as_ref().map(|p| p.expose());
+    if json {
+        let value = serde_json::json!({ "login": mach.login, "password": password });
+        writeln!(out, "{}", value)?;
+    } else {
+        writeln!(out, "{}", mach.login)?;
+        writeln!(out, "{}", password.unwrap_or(""))?;
+    }
+    Ok(())
+}
+
+fn set(path: &Path,
+       mut args: impl Iterator<Item = String>,
+       input: &mut dyn BufRead) -> Result<(), Failure> {
+    let mut login = None;
+    let mut password_stdin = false;
+    let mut host = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--login"                 => login = Some(value_of(&mut args, "--login")?),
+            "--password-stdin"        => password_stdin = true,
+            _ if arg.starts_with('-') => return usage(&format!("Unknown option `{}'", arg)),
+            _ if host.is_none()       => host = Some(arg),
+            _                         => return usage("Too many arguments"),
+        }
+    }
+    let host = match host {
+        Some(host) => host,
+        None       => return usage("Missing host"),
+    };
+    if login.is_none() && !password_stdin {
+        return usage("Nothing to set");
+    }
+    let password = if password_stdin {
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        Some(line)
+    } else {
+        None
+    };
+    let mut doc = read_document(path)?;
+    let fields = login.iter().map(|login| ("login", login))
+        .chain(password.iter().map(|password| ("password", password)));
+    if !fields.clone().all(|(keyword, value)| doc.set_field(Some(&host), keyword, value)) {
+        let mut mach = Machine::builder();
+        for (keyword, value) in fields {
+            mach = match keyword {
+                "login" => mach.login(value.as_str()),
+                _       => mach.password(value.as_str()),
+            };
+        }
+        doc.push_machine(&host, &mach.build());
+    }
+    doc.save_to_file(path, false)?;
+    Ok(())
+}
+
+fn delete(path: &Path, mut args: impl Iterator<Item = String>) -> Result<(), Failure> {
+    let host = match (args.next(), args.next()) {
+        (Some(host), None) => host,
+        (None, _)          => return usage("Missing host"),
+        (Some(_), Some(_)) => return usage("Too many arguments"),
+    };
+    let mut doc = read_document(path)?;
+    if !doc.remove_machine(&host) {
+        return Err(Failure::Other(format!("No entry for host `{}'", host)));
+    }
+    doc.save_to_file(path, false)?;
+    Ok(())
+}
+
+fn list(path: &Path,
+        mut args: impl Iterator<Item = String>,
+        out: &mut dyn Write) -> Result<(), Failure> {
+    if args.next().is_some() {
+        return usage("Too many arguments");
+    }
+    let netrc = read_document(path)?.to_netrc()?;
+    for (host, _) in &netrc.hosts {
+        writeln!(out, "{}", host)?;
+    }
+    if netrc.default.is_some() {
+        writeln!(out, "default")?;
+    }
+    Ok(())
+}
+
+fn fmt(path: &Path, args: impl Iterator<Item = String>) -> Result<(), Failure> {
+    let mut check = false;
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _         => return usage(&format!("Unknown argument `{}'", arg)),
+        }
+    }
+    let text = fs::read_to_string(path)?;
+    let formatted = Document::parse(&text)?.to_netrc()?.to_string_with(&NetrcFormat::canonical());
+    if formatted == text {
+        return Ok(());
+    }
+    if check {
+        return Err(Failure::Other(format!("`{}' is not formatted", path.display())));
+    }
+    Document::parse(&formatted)?.save_to_file(path, false)?;
+    Ok(())
+}
+
+/// Run `command` on the file at `path`, reading a password from `input`
+/// and printing to `out`.
+fn run_command(command: &str,
+               path: &Path,
+               args: impl Iterator<Item = String>,
+               input: &mut dyn BufRead,
+               out: &mut dyn Write) -> Result<(), Failure> {
+    match command {
+        "get"    => get(path, args, out),
+        "set"    => set(path, args, input),
+        "delete" => delete(path, args),
+        "list"   => list(path, args, out),
+        "fmt"    => fmt(path, args),
+        _        => usage(&format!("Unknown command `{}'", command)),
+    }
+}
+
+fn run() -> Result<(), Failure> {
+    let mut args = env::args().skip(1);
+    let mut path: Option<PathBuf> = None;
+    let command = loop {
+        match args.next() {
+            Some(arg) if arg == "--file"                => path = Some(value_of(&mut args, "--file")?.into()),
+            Some(arg) if arg == "-h" || arg == "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            Some(arg)                                   => break arg,
+            None                                        => return usage("Missing command"),
+        }
+    };
+    let path = match path.or_else(netrc::default_path) {
+        Some(path) => path,
+        None       => return Err(Failure::Other("No home directory to find netrc in".to_string())),
+    };
+    run_command(&command, &path, args, &mut io::stdin().lock(), &mut io::stdout().lock())
+}
+
+fn main() {
+    match run() {
+        Ok(())                   => {}
+        Err(Failure::Usage(msg)) => {
+            eprintln!("netrc: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+        Err(Failure::Other(msg)) => {
+            eprintln!("netrc: {}", msg);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::{run_command, Failure};
+
+    /// A file holding `text`, in a directory of its own named after `test`.
+    fn netrc_file(test: &str, text: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("netrc-cli-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("netrc");
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    /// Run `args` on `path` with `input` as standard input, returning what
+    /// was printed.
+    fn run(path: &Path, args: &[&str], input: &str) -> Result<String, Failure> {
+        let mut args = args.iter().map(|arg| arg.to_string());
+        let name = args.next().unwrap();
+        let mut out = Vec::new();
+        run_command(&name, path, args, &mut input.as_bytes(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn set_existing() {
+        let path = netrc_file("set-existing", "machine a.com login a password old # note\n");
+        run(&path, &["set", "a.com", "--password-stdin"], "new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(),
+                   "machine a.com login a password new # note\n");
+        assert_eq!(run(&path, &["get", "a.com"], "").unwrap(), "a\nnew\n");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn set_missing() {
+        let path = netrc_file("set-missing", "machine a.com login a\n");
+        run(&path, &["set", "b.com", "--login", "b", "--password-stdin"], "p w\r\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(),
+                   "machine a.com login a\nmachine b.com login b password \"p w\"\n");
+        assert_eq!(run(&path, &["list"], "").unwrap(), "a.com\nb.com\n");
+        assert!(matches!(run(&path, &["set", "c.com"], ""), Err(Failure::Usage(_))));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn delete() {
+        let path = netrc_file("delete", "machine a.com login a\nmachine b.com login b\n");
+        run(&path, &["delete", "a.com"], "").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "machine b.com login b\n");
+        assert!(matches!(run(&path, &["delete", "a.com"], ""), Err(Failure::Other(_))));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn fmt_check() {
+        let path = netrc_file("fmt-check", "machine a.com\n    login a\n    password p\n");
+        run(&path, &["fmt", "--check"], "").unwrap();
+
+        fs::write(&path, "machine a.com login a password p").unwrap();
+        assert!(matches!(run(&path, &["fmt", "--check"], ""), Err(Failure::Other(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "machine a.com login a password p");
+        run(&path, &["fmt"], "").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(),
+                   "machine a.com\n    login a\n    password p\n");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::parser::{Event, Parser, Sink};
+use crate::{same_host, Machine, Macro, Netrc, Result};
+
+/// A machine entry borrowing from the parsed input.  See `Machine`.  As
+/// with `Secret`, its `Debug` output hides the password and account.
+#[derive(Clone, Default)]
+pub struct MachineRef<'a> {
+    pub login: Cow<'a, str>,
+    pub password: Option<Cow<'a, str>>,
+    pub account: Option<Cow<'a, str>>,
+    pub port: Option<u16>,
+    pub port_name: Option<Cow<'a, str>>,
+    pub protocol: Option<Cow<'a, str>>,
+    pub extras: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    pub line: Option<usize>,
+}
+
+/// A macro borrowing from the parsed input.  See `Macro`.
+#[derive(Clone, Debug, Default)]
+pub struct MacroRef<'a> {
+    pub name: Cow<'a, str>,
+    pub body: Cow<'a, str>,
+    pub lines: Option<(usize, usize)>,
+}
+
+/// A `Netrc` whose strings borrow from the parsed input instead of being
+/// copied.  Built by `Netrc::parse_borrowed`.
+#[derive(Clone, Debug, Default)]
+pub struct NetrcRef<'a> {
+    pub hosts: Vec<(Cow<'a, str>, MachineRef<'a>)>,
+    pub default: Option<MachineRef<'a>>,
+    pub macros: Vec<MacroRef<'a>>,
+    pub lines: Option<usize>,
+}
+
+/// Formats as `[redacted]`, like `Secret`.
+struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl fmt::Debug for MachineRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MachineRef")
+            .field("login", &self.login)
+            .field("password", &self.password.as_ref().map(|_| Redacted))
+            .field("account", &self.account.as_ref().map(|_| Redacted))
+            .field("port", &self.port)
+            .field("port_name", &self.port_name)
+            .field("protocol", &self.protocol)
+            .field("extras", &self.extras)
+            .field("line", &self.line)
+            .finish()
+    }
+}
+
+impl<'a> MachineRef<'a> {
+    pub fn into_owned(self) -> Machine {
+        Machine {
+            login: self.login.into_owned(),
+            password: self.password.map(|v| v.into_owned().into()),
+            account: self.account.map(|v| v.into_owned().into()),
+            port: self.port,
+            port_name: self.port_name.map(Cow::into_owned),
+            protocol: self.protocol.map(Cow::into_owned),
+            extras: self.extras.into_iter()
+                .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                .collect(),
+            line: self.line,
+        }
+    }
+}
+
+impl<'a> MacroRef<'a> {
+    pub fn into_owned(self) -> Macro {
+        Macro {
+            name: self.name.into_owned(),
+            body: self.body.into_owned(),
+            lines: self.lines,
+        }
+    }
+}
+
+impl<'a> NetrcRef<'a> {
+    /// Look up the first `machine` entry named `host`, like `Netrc::get`.
+    pub fn get(&self, host: &str) -> Option<&MachineRef<'a>> {
+        self.hosts.iter().find(|(name, _)| same_host(name, host)).map(|(_, mach)| mach)
+    }
+
+    pub fn into_owned(self) -> Netrc {
+        Netrc {
+            hosts: self.hosts.into_iter()
+                .map(|(name, mach)| (name.into_owned(), mach.into_owned()))
+                .collect(),
+            default: self.default.map(MachineRef::into_owned),
+            macros: self.macros.into_iter().map(MacroRef::into_owned).collect(),
+            lines: self.lines,
+        }
+    }
+}
+
+impl Netrc {
+    /// Parse a netrc held in memory without copying its tokens: the strings
+    /// of the result point into `input`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let input = "machine example.com login foo password bar";
+    /// let netrc = Netrc::parse_borrowed(input).unwrap();
+    /// assert_eq!(netrc.hosts[0].1.login, "foo");
+    /// ```
+    pub fn parse_borrowed(input: &str) -> Result<NetrcRef<'_>> {
+        let options = Default::default();
+        let mut parser = Parser::new(&options, BorrowedBuilder::new(input));
+        for line in input.split_inclusive('\n') {
+            parser.feed_line(line)?;
+        }
+        let lines = parser.lines;
+        let mut netrc = parser.finish()?.netrc;
+        netrc.lines = Some(lines);
+        Ok(netrc)
+    }
+}
+
+/// A `Sink` building a `NetrcRef` that borrows from `input`.
+struct BorrowedBuilder<'a> {
+    input: &'a str,
+    netrc: NetrcRef<'a>,
+    /// The machine taking fields: `None` for `default`, else a host index.
+    current: Option<usize>,
+}
+
+impl<'a> BorrowedBuilder<'a> {
+    fn new(input: &'a str) -> BorrowedBuilder<'a> {
+        BorrowedBuilder { input, netrc: Default::default(), current: None }
+    }
+
+    fn current_machine(&mut self) -> &mut MachineRef<'a> {
+        match self.current {
+            Some(n) => &mut self.netrc.hosts[n].1,
+            None    => self.netrc.default.as_mut().unwrap(),
+        }
+    }
+}
+
+/// Append `text` to `body`, extending the borrowed slice of `input` when
+/// `text` directly follows it.
+fn append<'a>(input: &'a str, body: &mut Cow<'a, str>, text: &'a str) {
+    let base = input.as_ptr() as usize;
+    match *body {
+        Cow::Borrowed("") => *body = Cow::Borrowed(text),
+        Cow::Borrowed(b) if b.as_ptr() as usize + b.len()
+                            == text.as_ptr() as usize => {
+            let start = b.as_ptr() as usize - base;
+            let end = text.as_ptr() as usize - base + text.len();
+            *body = Cow::Borrowed(&input[start..end]);
+        }
+        _ => body.to_mut().push_str(text),
+    }
+}
+
+impl<'a> Sink<'a> for BorrowedBuilder<'a> {
+    fn event(&mut self, event: Event<'a>, line: usize) {
+        match event {
+            Event::Machine(name) => {
+                let mach = MachineRef { line: Some(line), ..Default::default() };
+                self.netrc.hosts.push((name, mach));
+                self.current = Some(self.netrc.hosts.len() - 1);
+            }
+            Event::Default => {
+                self.netrc.default = Some(MachineRef { line: Some(line), ..Default::default() });
+                self.current = None;
+            }
+            Event::Login(v)    => self.current_machine().login = v,
+            Event::Password(v) => self.current_machine().password = Some(v),
+            Event::Account(v)  => self.current_machine().account = Some(v),
+            Event::Port(port)  => {
+                let m = self.current_machine();
+                m.port = Some(port);
+                m.port_name = None;
+            }
+            Event::PortName(v) => {
+                let m = self.current_machine();
+                m.port = None;
+                m.port_name = Some(v);
+            }
+            Event::Protocol(v) => self.current_machine().protocol = Some(v),
+            Event::Extra(k, v) => self.current_machine().extras.push((k, v)),
+            Event::MacroDef(name) => self.netrc.macros.push(MacroRef {
+                name,
+                body: Cow::Borrowed(""),
+                lines: Some((line, line)),
+            }),
+            Event::MacroText(text) => {
+                let mac = self.netrc.macros.last_mut().unwrap();
+                append(self.input, &mut mac.body, text);
+            }
+            Event::MacroEnd => {
+                let mac = self.netrc.macros.last_mut().unwrap();
+                if let Some((_, ref mut end)) = mac.lines {
+                    *end = line;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::{Error, Netrc};
+    use std::borrow::Cow;
+    use std::io::BufReader;
+
+    fn points_into(input: &str, s: &str) -> bool {
+        input.as_bytes().as_ptr_range().contains(&s.as_ptr())
+    }
+
+    #[test]
+    fn parse_borrowed() {
+        let input = "machine example.com login test password p@ssw0rd port 42
+                     macdef init
+                            cd /pub
+                            bin
+
+                     default login def";
+        let netrc = Netrc::parse_borrowed(input).unwrap();
+        let (ref name, ref mach) = netrc.hosts[0];
+        assert_eq!(name, "example.com");
+        assert!(points_into(input, name));
+        assert!(points_into(input, &mach.login));
+        assert!(points_into(input, mach.password.as_ref().unwrap()));
+        assert_eq!(mach.port, Some(42));
+        assert!(points_into(input, &netrc.macros[0].body));
+        assert!(points_into(input, &netrc.default.as_ref().unwrap().login));
+
+        let owned = Netrc::parse(BufReader::new(input.as_bytes())).unwrap();
+        assert_eq!(netrc.into_owned().to_string(), owned.to_string());
+    }
+
+    #[test]
+    fn parse_borrowed_no_copy() {
+        let input = "machine a.com login a password p account x protocol ftp
+                     machine b.com login b
+                     macdef init
+                     bin
+
+";
+        let netrc = Netrc::parse_borrowed(input).unwrap();
+        let mach = netrc.get("B.COM").unwrap();
+        assert_eq!(mach.login, "b");
+        assert!(netrc.get("c.com").is_none());
+        for (name, mach) in &netrc.hosts {
+            let fields = [&mach.password, &mach.account, &mach.protocol];
+            assert!(matches!(name, Cow::Borrowed(_)));
+            assert!(matches!(mach.login, Cow::Borrowed(_)));
+            for field in fields.iter().filter_map(|f| f.as_ref()) {
+                assert!(matches!(field, Cow::Borrowed(_)));
+            }
+        }
+        assert!(matches!(netrc.macros[0].body, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn parse_borrowed_error() {
+        match Netrc::parse_borrowed("machine foo.com\nlogin").unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Missing value for `login'");
+                assert_eq!(line, 2);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use alloc::format;
+use alloc::string::String;
+
+use crate::{Machine, Macro, Netrc};
+
+/// Builds a `Machine` field by field.  See `Machine::builder`.
+#[derive(Clone, Debug, Default)]
+pub struct MachineBuilder {
+    machine: Machine,
+}
+
+impl MachineBuilder {
+    pub fn login<T: Into<String>>(mut self, login: T) -> MachineBuilder {
+        self.machine.login = login.into();
+        self
+    }
+
+    pub fn password<T: Into<String>>(mut self, password: T) -> MachineBuilder {
+        self.machine.password = Some(password.into().into());
+        self
+    }
+
+    pub fn account<T: Into<String>>(mut self, account: T) -> MachineBuilder {
+        self.machine.account = Some(account.into().into());
+        self
+    }
+
+    /// Set the port number, replacing any port name.
+    pub fn port(mut self, port: u16) -> MachineBuilder {
+        self.machine.port = Some(port);
+        self.machine.port_name = None;
+        self
+    }
+
+    /// Set a service name such as `imaps` as the port, replacing any port
+    /// number.
+    pub fn port_name<T: Into<String>>(mut self, name: T) -> MachineBuilder {
+        self.machine.port = None;
+        self.machine.port_name = Some(name.into());
+        self
+    }
+
+    pub fn protocol<T: Into<String>>(mut self, protocol: T) -> MachineBuilder {
+        self.machine.protocol = Some(protocol.into());
+        self
+    }
+
+    /// Add a field with another keyword, such as `force yes` in
+    /// `.authinfo` files.
+    pub fn extra<K: Into<String>, V: Into<String>>(mut self, keyword: K, value: V) -> MachineBuilder {
+        self.machine.extras.push((keyword.into(), value.into()));
+        self
+    }
+
+    pub fn build(self) -> Machine {
+        self.machine
+    }
+}
+
+/// Builds a `Netrc` entry by entry, for generating netrc files.  See
+/// `Netrc::builder`.
+#[derive(Clone, Debug, Default)]
+pub struct NetrcBuilder {
+    netrc: Netrc,
+}
+
+impl NetrcBuilder {
+    /// Append a `machine` entry.
+    pub fn machine<T: Into<String>>(mut self, host: T, machine: Machine) -> NetrcBuilder {
+        self.netrc.hosts.push((host.into(), machine));
+        self
+    }
+
+    /// Set the `default` entry.
+    pub fn default_machine(mut self, machine: Machine) -> NetrcBuilder {
+        self.netrc.default = Some(machine);
+        self
+    }
+
+    /// Append a macro running `commands`, one per line.  Its body is laid
+    /// out as if parsed, so that `Macro::body_trimmed` returns `commands`.
+    pub fn macro_def<T: Into<String>>(mut self, name: T, commands: &str) -> NetrcBuilder {
+        let commands = commands.trim_end_matches(['\n', '\r']);
+        self.netrc.macros.push(Macro {
+            name: name.into(),
+            body: format!("\n{}\n\n", commands),
+            lines: None,
+        });
+        self
+    }
+
+    pub fn build(self) -> Netrc {
+        self.netrc
+    }
+}
+
+impl Machine {
+    /// Start building a `Machine` with no fields set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Machine;
+    ///
+    /// let mach = Machine::builder().login("foo").password("bar").port(21).build();
+    /// assert_eq!(mach.login, "foo");
+    /// assert_eq!(mach.password.unwrap().expose(), "bar");
+    /// ```
+    pub fn builder() -> MachineBuilder {
+        MachineBuilder::default()
+    }
+}
+
+impl Netrc {
+    /// Start building an empty `Netrc`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::{Machine, Netrc};
+    ///
+    /// let netrc = Netrc::builder()
+    ///     .machine("example.com", Machine::builder().login("foo").build())
+    ///     .default_machine(Machine::builder().login("anonymous").build())
+    ///     .build();
+    /// assert_eq!(netrc.to_string(), "machine example.com login foo\ndefault login anonymous\n");
+    /// ```
+    pub fn builder() -> NetrcBuilder {
+        NetrcBuilder::default()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::{Machine, Netrc};
+
+    #[test]
+    fn build_round_trip() {
+        let mach = Machine::builder()
+            .login("test")
+            .password("p@ss")
+            .account("acct")
+            .port_name("imaps")
+            .port(993)
+            .protocol("imap")
+            .build();
+        assert_eq!(mach.port, Some(993));
+        assert!(mach.port_name.is_none());
+        let netrc = Netrc::builder()
+            .machine("a.com", mach)
+            .machine("b.com", Machine::builder().login("b").build())
+            .macro_def("init", "cd /pub\nbin\n")
+            .build();
+        assert_eq!(netrc.macros[0].body_trimmed(), "cd /pub\nbin");
+
+        let parsed = Netrc::parse(netrc.to_string().as_bytes()).unwrap();
+        assert_eq!(parsed.to_string(), netrc.to_string());
+        assert_eq!(parsed.macros[0].body, netrc.macros[0].body);
+        let mach = &parsed.hosts[0].1;
+        assert_eq!(mach.account.as_ref().unwrap().expose(), "acct");
+        assert_eq!(mach.protocol.as_deref(), Some("imap"));
+    }
+}
@@ -0,0 +1,89 @@
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+
+use crate::{Machine, Netrc};
+
+/// Interpret a host name as a network: either a CIDR range or a single
+/// address, which is treated as a full-length prefix.
+fn host_net(host: &str) -> Option<IpNet> {
+    host.parse().ok().or_else(|| host.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// Reject a host name that looks like a CIDR range but does not parse.
+pub(crate) fn check_host(host: &str) -> Result<(), String> {
+    if host.contains('/') && host.parse::<IpNet>().is_err() {
+        return Err(format!("Invalid CIDR range `{}'", host));
+    }
+    Ok(())
+}
+
+impl Netrc {
+    /// Look up the machine whose host is a CIDR range (or address)
+    /// containing `ip`.  When several ranges match, the one with the longest
+    /// prefix wins; among equally specific ranges, the first one does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::{Netrc, ParseOptions};
+    /// use std::io::Cursor;
+    ///
+    /// let input: Cursor<&[u8]> =
+    ///   Cursor::new(b"machine 10.0.0.0/8 login svc");
+    /// let options = ParseOptions { allow_cidr: true, ..Default::default() };
+    /// let netrc = Netrc::parse_with_options(input, &options).unwrap();
+    /// let mach = netrc.get_ip("10.1.2.3".parse().unwrap()).unwrap();
+    /// assert_eq!(mach.login, "svc");
+    /// ```
+    pub fn get_ip(&self, ip: IpAddr) -> Option<&Machine> {
+        let mut best: Option<(u8, &Machine)> = None;
+        for (host, mach) in &self.hosts {
+            let net = match host_net(host) {
+                Some(net) if net.contains(&ip) => net,
+                _                              => continue,
+            };
+            match best {
+                Some((len, _)) if len >= net.prefix_len() => (),
+                _ => best = Some((net.prefix_len(), mach)),
+            }
+        }
+        best.map(|(_, mach)| mach)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Netrc, ParseOptions};
+    use std::io::BufReader;
+
+    fn parse(input: &str) -> crate::Result<Netrc> {
+        let options = ParseOptions { allow_cidr: true, ..Default::default() };
+        Netrc::parse_with_options(BufReader::new(input.as_bytes()), &options)
+    }
+
+    #[test]
+    fn get_ip_most_specific() {
+        let netrc = parse("machine 10.0.0.0/8 login wide
+                           machine 10.1.0.0/16 login narrow
+                           machine 10.1.2.3 login exact
+                           machine example.com login name").unwrap();
+        let get = |ip: &str| netrc.get_ip(ip.parse().unwrap());
+        assert_eq!(get("10.200.0.1").unwrap().login, "wide");
+        assert_eq!(get("10.1.9.9").unwrap().login, "narrow");
+        assert_eq!(get("10.1.2.3").unwrap().login, "exact");
+        assert!(get("192.168.0.1").is_none());
+        assert!(get("::1").is_none());
+    }
+
+    #[test]
+    fn parse_error_cidr() {
+        match parse("machine 10.0.0.0/33 login svc").unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Invalid CIDR range `10.0.0.0/33'");
+                assert_eq!(line, 1);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+}
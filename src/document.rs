@@ -0,0 +1,488 @@
+//! Editing a netrc file in place, keeping its layout.
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::parser::Tokens;
+use crate::{same_host, Machine, MachineFields, Netrc, ParseOptions, Result, Value};
+
+/// A piece of a `Document`.  The pieces concatenated give back the text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+    /// Whitespace, comments and macro bodies.
+    Trivia(String),
+    /// A keyword such as `machine` or `login`.
+    Keyword(String),
+    /// The word following a keyword, quotes included.
+    Value(String),
+}
+
+/// What the next word of the input is.
+#[derive(Clone, Copy)]
+enum Expect {
+    Keyword,
+    Value,
+    MacroName,
+    MacroBody,
+}
+
+/// A netrc file kept word for word, along with the whitespace, comments
+/// and macro bodies between the words, so that editing an entry leaves
+/// the rest of the text untouched.
+///
+/// Input is read with `ParseOptions.quoted_values` and
+/// `ParseOptions.comments`.
+///
+/// # Examples
+///
+/// ```
+/// use netrc::document::Document;
+///
+/// let mut doc = Document::parse("# Work\nmachine example.com  login foo  password old\n")
+///     .unwrap();
+/// doc.set_field(Some("example.com"), "password", "new");
+/// assert_eq!(doc.to_string(), "# Work\nmachine example.com  login foo  password new\n");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Document {
+    pieces: Vec<Piece>,
+}
+
+/// The options `Document` reads its input with.
+fn options() -> ParseOptions {
+    ParseOptions { quoted_values: true, comments: true, ..Default::default() }
+}
+
+fn is_entry_start(keyword: &str) -> bool {
+    matches!(keyword, "machine" | "default" | "macdef")
+}
+
+fn is_field(keyword: &str) -> bool {
+    matches!(keyword, "login" | "password" | "account" | "port" | "protocol")
+}
+
+/// A word as the parser reads it, without quotes.
+fn unquote(raw: &str) -> Cow<'_, str> {
+    Tokens::new(raw, false).next(true).unwrap_or_default()
+}
+
+impl Document {
+    /// Read `text`, failing as parsing it into a `Netrc` would.
+    pub fn parse(text: &str) -> Result<Document> {
+        Netrc::parse_str(text, &options())?;
+        let mut doc = Document::default();
+        doc.push_text(text);
+        Ok(doc)
+    }
+
+    /// Parse the current text into a `Netrc`.
+    pub fn to_netrc(&self) -> Result<Netrc> {
+        Netrc::parse_str(&self.to_string(), &options())
+    }
+
+    /// Set `keyword`, such as `password`, to `value` in the first
+    /// `machine` entry for `host`, or in the `default` entry if `host` is
+    /// `None`.  The field is added at the end of the entry if missing.
+    /// Returns whether there is such an entry and `keyword` is a field.
+    pub fn set_field(&mut self, host: Option<&str>, keyword: &str, value: &str) -> bool {
+        if !is_field(keyword) {
+            return false;
+        }
+        let (start, end) = match self.entry(host) {
+            Some(range) => range,
+            None        => return false,
+        };
+        let value = Value(value).to_string();
+        match self.field(start, end, keyword) {
+            Some((_, v)) => self.pieces[v] = Piece::Value(value),
+            None         => {
+                let field = vec![Piece::Trivia(" ".to_string()),
+                                 Piece::Keyword(keyword.to_string()),
+                                 Piece::Trivia(" ".to_string()),
+                                 Piece::Value(value)];
+                self.pieces.splice(end..end, field);
+                self.merge_trivia();
+            }
+        }
+        true
+    }
+
+    /// Remove `keyword` and its value from the first `machine` entry for
+    /// `host`, or from the `default` entry if `host` is `None`.  Returns
+    /// whether there was such a field.
+    pub fn remove_field(&mut self, host: Option<&str>, keyword: &str) -> bool {
+        let field = self.entry(host).and_then(|(start, end)| self.field(start, end, keyword));
+        match field {
+            Some((k, v)) => {
+                self.remove_words(k, v + 1);
+                true
+            }
+            None         => false,
+        }
+    }
+
+    /// Remove the first `machine` entry for `host`, along with its lines
+    /// if nothing but comments is left on them.  Returns whether there was
+    /// one.
+    pub fn remove_machine(&mut self, host: &str) -> bool {
+        match self.entry(Some(host)) {
+            Some((start, end)) => {
+                self.remove_words(start, end);
+                true
+            }
+            None               => false,
+        }
+    }
+
+    /// Append a `machine` entry on a line of its own.
+    pub fn push_machine(&mut self, host: &str, machine: &Machine) {
+        let text = self.to_string();
+        if !text.is_empty() && !text.ends_with('\n') {
+            self.push_trivia("\n");
+        }
+        if self.in_macro() {
+            self.push_trivia("\n");
+        }
+        self.push_text(&format!("machine {}{}\n", Value(host), MachineFields(machine, " ")));
+    }
+
+    /// Whether the document ends in a macro missing its blank line.
+    fn in_macro(&self) -> bool {
+        let words: Vec<usize> = self.words().collect();
+        let (keyword, name) = match *words.as_slice() {
+            [.., keyword, name] => (keyword, name),
+            _                   => return false,
+        };
+        if self.keyword(keyword).as_deref() != Some("macdef") {
+            return false;
+        }
+        match self.pieces.get(name + 1) {
+            Some(Piece::Trivia(body)) => !body.split_inclusive('\n').skip(1)
+                .any(|line| line.trim().is_empty()),
+            _                         => true,
+        }
+    }
+
+    /// Split `text` into pieces appended to the document, which must be at
+    /// the start of an entry.
+    fn push_text(&mut self, text: &str) {
+        let mut expect = Expect::Keyword;
+        let text = match text.strip_prefix('\u{feff}') {
+            Some(rest) => {
+                self.push_trivia("\u{feff}");
+                rest
+            }
+            None       => text,
+        };
+        for line in text.split_inclusive('\n') {
+            if let Expect::MacroBody = expect {
+                self.push_trivia(line);
+                if line.trim().is_empty() {
+                    expect = Expect::Keyword;
+                }
+                continue;
+            }
+            let mut tokens = Tokens::new(line, false);
+            loop {
+                let rest = tokens.remaining();
+                let word = match tokens.next(true) {
+                    Some(word) => word,
+                    None       => {
+                        self.push_trivia(rest);
+                        break;
+                    }
+                };
+                let raw = &rest[..rest.len() - tokens.remaining().len()];
+                let space = raw.len() - raw.trim_start().len();
+                self.push_trivia(&raw[..space]);
+                let raw = raw[space..].to_string();
+                match expect {
+                    Expect::Keyword if word.starts_with('#') => {
+                        self.push_trivia(&rest[space..]);
+                        break;
+                    }
+                    Expect::Keyword   => {
+                        expect = match &*word {
+                            "default" => Expect::Keyword,
+                            "macdef"  => Expect::MacroName,
+                            _         => Expect::Value,
+                        };
+                        self.pieces.push(Piece::Keyword(raw));
+                    }
+                    Expect::Value     => {
+                        expect = Expect::Keyword;
+                        self.pieces.push(Piece::Value(raw));
+                    }
+                    Expect::MacroName => {
+                        expect = Expect::MacroBody;
+                        self.pieces.push(Piece::Value(raw));
+                        self.push_trivia(tokens.remaining());
+                        break;
+                    }
+                    Expect::MacroBody => unreachable!(),
+                }
+            }
+        }
+    }
+
+    fn push_trivia(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match self.pieces.last_mut() {
+            Some(Piece::Trivia(trivia)) => trivia.push_str(text),
+            _                           => self.pieces.push(Piece::Trivia(text.to_string())),
+        }
+    }
+
+    /// Join adjacent trivia and drop empty trivia.
+    fn merge_trivia(&mut self) {
+        let pieces = core::mem::take(&mut self.pieces);
+        for piece in pieces {
+            match piece {
+                Piece::Trivia(text) => self.push_trivia(&text),
+                piece               => self.pieces.push(piece),
+            }
+        }
+    }
+
+    /// The keyword at `i`, if the piece there is one.
+    fn keyword(&self, i: usize) -> Option<Cow<'_, str>> {
+        match self.pieces[i] {
+            Piece::Keyword(ref raw) => Some(unquote(raw)),
+            _                       => None,
+        }
+    }
+
+    /// The indices of the words, skipping trivia.
+    fn words(&self) -> impl Iterator<Item = usize> + '_ {
+        self.pieces.iter().enumerate()
+            .filter(|(_, piece)| !matches!(piece, Piece::Trivia(_)))
+            .map(|(i, _)| i)
+    }
+
+    /// The pieces of the first `machine` entry for `host`, or of the
+    /// `default` entry, from its keyword to just after its last word.
+    fn entry(&self, host: Option<&str>) -> Option<(usize, usize)> {
+        let words: Vec<usize> = self.words().collect();
+        let mut start = None;
+        let mut end = 0;
+        for (n, &i) in words.iter().enumerate() {
+            let keyword = match self.keyword(i) {
+                Some(keyword) => keyword,
+                None          => continue,
+            };
+            if !is_entry_start(&keyword) {
+                continue;
+            }
+            if start.is_some() {
+                break;
+            }
+            let found = match (&*keyword, host) {
+                ("machine", Some(host)) => words.get(n + 1).is_some_and(|&v| {
+                    matches!(self.pieces[v],
+                             Piece::Value(ref name) if same_host(&unquote(name), host))
+                }),
+                ("default", None)       => true,
+                _                       => false,
+            };
+            if found {
+                start = Some(i);
+            }
+        }
+        let start = start?;
+        for i in self.words().skip_while(|&i| i < start) {
+            if i > start && self.keyword(i).is_some_and(|k| is_entry_start(&k)) {
+                break;
+            }
+            end = i + 1;
+        }
+        Some((start, end))
+    }
+
+    /// The indices of the `keyword` piece of a field within `start..end`
+    /// and of its value.
+    fn field(&self, start: usize, end: usize, keyword: &str) -> Option<(usize, usize)> {
+        let mut words = self.words().skip_while(|&i| i < start).take_while(|&i| i < end);
+        while let Some(i) = words.next() {
+            match self.keyword(i) {
+                Some(k) if k == "default" => {}
+                Some(k)                   => {
+                    let value = words.next()?;
+                    if k == keyword {
+                        return Some((i, value));
+                    }
+                }
+                None                      => {}
+            }
+        }
+        None
+    }
+
+    /// Remove the pieces `start..end`, from a word to just after a word,
+    /// with the whitespace setting them apart: their lines if nothing but
+    /// comments is left on them, else the space before or after.
+    fn remove_words(&mut self, start: usize, end: usize) {
+        let trivia = |piece: Option<&Piece>| match piece {
+            Some(Piece::Trivia(text)) => Some(text.clone()),
+            _                         => None,
+        };
+        let before = trivia(start.checked_sub(1).map(|i| &self.pieces[i]));
+        let after = trivia(self.pieces.get(end));
+        // Where the line of `start` begins in `before`.
+        let line_start = match before {
+            None                                             => Some(0),
+            Some(ref t) if t.contains('\n')                  =>
+                t.rfind('\n').map(|i| i + 1),
+            Some(ref t) if start == 1 && t.trim_start_matches('\u{feff}').trim().is_empty() =>
+                Some(t.len() - t.trim_start_matches('\u{feff}').len()),
+            Some(_)                                          => None,
+        };
+        let after_line = after.as_deref()
+            .and_then(|t| t.split_inclusive('\n').next())
+            .unwrap_or("");
+        let at_eof = end + 1 >= self.pieces.len();
+        let line_end = (after_line.ends_with('\n') || at_eof) && {
+            let rest = after_line.trim();
+            rest.is_empty() || rest.starts_with('#')
+        };
+        let after_line = after_line.len();
+        let (before, after) = match (before, after) {
+            // Take the whole lines, from the indentation to the line break.
+            (before, after) if line_start.is_some() && line_end => {
+                let keep = line_start.unwrap_or(0);
+                (before.map(|t| t[..keep].to_string()),
+                 after.map(|t| t[after_line..].to_string()))
+            }
+            (Some(_), after) if line_start.is_none() => (Some(String::new()), after),
+            (before, Some(a))                        => {
+                let rest = a.trim_start_matches(|c: char| c != '\n' && c.is_whitespace());
+                (before, Some(rest.to_string()))
+            }
+            (before, None)                           => (before, None),
+        };
+        if let Some(text) = after {
+            self.pieces[end] = Piece::Trivia(text);
+        }
+        self.pieces.drain(start..end);
+        if let Some(text) = before {
+            self.pieces[start - 1] = Piece::Trivia(text);
+        }
+        self.merge_trivia();
+    }
+}
+
+/// The text of the document, byte for byte as read where it was not
+/// edited.
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for piece in &self.pieces {
+            match *piece {
+                Piece::Trivia(ref text)
+                | Piece::Keyword(ref text)
+                | Piece::Value(ref text) => f.write_str(text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::Document;
+    use crate::{Error, Machine};
+
+    const INPUT: &str = "\u{feff}# Personal hosts
+machine a.com   login a   password pa   # old
+machine b.com
+    login b
+    password pb
+
+macdef init
+cd /pub   # not a comment
+bin
+
+default login anonymous \"password\" \"me at home\"
+";
+
+    #[test]
+    fn round_trip() {
+        let doc = Document::parse(INPUT).unwrap();
+        assert_eq!(doc.to_string(), INPUT);
+        let netrc = doc.to_netrc().unwrap();
+        assert_eq!(netrc.hosts.len(), 2);
+        assert_eq!(netrc.default.unwrap().password.unwrap().expose(), "me at home");
+    }
+
+    #[test]
+    fn set_field() {
+        let mut doc = Document::parse(INPUT).unwrap();
+        assert!(doc.set_field(Some("A.com"), "password", "new"));
+        assert!(doc.set_field(Some("b.com"), "port", "21"));
+        assert!(doc.set_field(None, "login", "guest user"));
+        assert!(!doc.set_field(Some("c.com"), "login", "c"));
+        let expected = INPUT.replace("password pa ", "password new ")
+            .replace("password pb\n", "password pb port 21\n")
+            .replace("login anonymous", "login \"guest user\"");
+        assert_eq!(doc.to_string(), expected);
+        let netrc = doc.to_netrc().unwrap();
+        assert_eq!(netrc.hosts[1].1.port, Some(21));
+        assert_eq!(netrc.default.unwrap().login, "guest user");
+    }
+
+    #[test]
+    fn remove() {
+        let mut doc = Document::parse(INPUT).unwrap();
+        assert!(doc.remove_field(Some("b.com"), "login"));
+        assert!(doc.remove_field(Some("a.com"), "login"));
+        assert!(!doc.remove_field(Some("a.com"), "account"));
+        let expected = INPUT.replace("    login b\n", "").replace("login a   ", "");
+        assert_eq!(doc.to_string(), expected);
+
+        assert!(doc.remove_machine("b.com"));
+        assert!(doc.remove_machine("a.com"));
+        assert!(!doc.remove_machine("a.com"));
+        assert_eq!(doc.to_string(), "\u{feff}# Personal hosts
+
+macdef init
+cd /pub   # not a comment
+bin
+
+default login anonymous \"password\" \"me at home\"
+");
+        assert!(doc.to_netrc().unwrap().hosts.is_empty());
+    }
+
+    #[test]
+    fn push_machine() {
+        let mach = Machine { login: "c".to_string(), ..Default::default() };
+        let mut doc = Document::parse("machine a.com login a").unwrap();
+        doc.push_machine("c.com", &mach);
+        assert_eq!(doc.to_string(), "machine a.com login a\nmachine c.com login c\n");
+
+        let mut doc = Document::parse("macdef init\nbin\n").unwrap();
+        doc.push_machine("c.com", &mach);
+        assert_eq!(doc.to_string(), "macdef init\nbin\n\nmachine c.com login c\n");
+        assert_eq!(doc.to_netrc().unwrap().hosts[0].1.login, "c");
+        assert!(doc.set_field(Some("c.com"), "password", "p"));
+        assert_eq!(doc.to_netrc().unwrap().hosts[0].1.password.as_ref().unwrap().expose(), "p");
+
+        let mach = Machine::builder().login("d").password("\"abc").build();
+        doc.push_machine("d.com", &mach);
+        assert!(doc.to_string().ends_with("machine d.com login d password \"\\\"abc\"\n"));
+        let netrc = doc.to_netrc().unwrap();
+        assert_eq!(netrc.hosts[1].1.password.as_ref().unwrap().expose(), "\"abc");
+    }
+
+    #[test]
+    fn parse_error() {
+        match Document::parse("machine a.com\nfoo").unwrap_err() {
+            Error::UnknownEntry { line, .. } => assert_eq!(line, 2),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+}
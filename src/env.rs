@@ -0,0 +1,193 @@
+use std::env;
+
+use crate::{Error, Machine, Netrc, Result};
+
+/// What `Netrc::expand_env` does with a reference to an environment
+/// variable that is not set, or not valid Unicode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UndefinedVarPolicy {
+    /// Fail with `Error::UndefinedVariable`.
+    #[default]
+    Error,
+    /// Leave the reference in place, `$` included.
+    Keep,
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Replace every `${NAME}` and `$NAME` in `value`, and every `$$` by a
+/// single `$`.  A `$` not followed by a variable name, or an unclosed `${`,
+/// is kept as is.
+fn expand(value: &str, policy: UndefinedVarPolicy) -> Result<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) if braced[..end].starts_with(is_name_start)
+                             && braced[..end].chars().all(is_name_char) =>
+                    (&braced[..end], end + 2),
+                _ => ("", 0),
+            },
+            None if after.starts_with(is_name_start) => {
+                let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+                (&after[..end], end)
+            }
+            None => ("", 0),
+        };
+        let reference = &rest[i..i + 1 + len];
+        rest = &rest[i + 1 + len..];
+        if name.is_empty() {
+            out.push_str(reference);
+            continue;
+        }
+        match (env::var(name), policy) {
+            (Ok(v), _)                          => out.push_str(&v),
+            (Err(_), UndefinedVarPolicy::Keep)  => out.push_str(reference),
+            (Err(_), UndefinedVarPolicy::Error) =>
+                return Err(Error::UndefinedVariable(name.to_string())),
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+impl Machine {
+    /// Replace references to environment variables in `login`, `password`
+    /// and `account`, as `Netrc::expand_env` does, for expanding only the
+    /// entry a lookup found.  On error `self` is unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::{Netrc, UndefinedVarPolicy};
+    ///
+    /// std::env::set_var("NETRC_DOC_DEPLOY_TOKEN", "t0ken");
+    /// let input: &[u8] = b"machine a.com password ${NETRC_DOC_DEPLOY_TOKEN}
+    ///                      machine b.com password ${NETRC_DOC_UNSET}";
+    /// let netrc = Netrc::parse(input).unwrap();
+    /// let mut mach = netrc.get("a.com").unwrap().clone();
+    /// mach.expand_env(UndefinedVarPolicy::Error).unwrap();
+    /// assert_eq!(mach.password.unwrap().expose(), "t0ken");
+    /// ```
+    pub fn expand_env(&mut self, policy: UndefinedVarPolicy) -> Result<()> {
+        let login = expand(&self.login, policy)?;
+        let password = match self.password {
+            Some(ref password) => Some(expand(password.expose(), policy)?.into()),
+            None               => None,
+        };
+        let account = match self.account {
+            Some(ref account) => Some(expand(account.expose(), policy)?.into()),
+            None              => None,
+        };
+        self.login = login;
+        self.password = password;
+        self.account = account;
+        Ok(())
+    }
+}
+
+impl Netrc {
+    /// Replace references to environment variables, written `${NAME}` or
+    /// `$NAME`, in the `login`, `password` and `account` of every machine,
+    /// `default` included.  `$$` stands for a literal `$`.  Macro bodies are
+    /// left alone.  On error `self` is unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::{Netrc, UndefinedVarPolicy};
+    ///
+    /// std::env::set_var("NETRC_DOC_TOKEN", "s3cret");
+    /// let input: &[u8] = b"machine example.com login foo password ${NETRC_DOC_TOKEN}";
+    /// let mut netrc = Netrc::parse(input).unwrap();
+    /// netrc.expand_env(UndefinedVarPolicy::Error).unwrap();
+    /// assert_eq!(netrc.hosts[0].1.password.as_ref().unwrap().expose(), "s3cret");
+    /// ```
+    pub fn expand_env(&mut self, policy: UndefinedVarPolicy) -> Result<()> {
+        let mut hosts = self.hosts.clone();
+        let mut default = self.default.clone();
+        for (_, mach) in &mut hosts {
+            mach.expand_env(policy)?;
+        }
+        if let Some(ref mut mach) = default {
+            mach.expand_env(policy)?;
+        }
+        self.hosts = hosts;
+        self.default = default;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Netrc, UndefinedVarPolicy};
+    use std::io::BufReader;
+
+    fn parse(input: &str) -> Netrc {
+        Netrc::parse(BufReader::new(input.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn expand_env_set() {
+        std::env::set_var("NETRC_TEST_TOKEN", "t0ken");
+        std::env::set_var("NETRC_TEST_USER", "bob");
+        let mut netrc = parse("machine example.com login $NETRC_TEST_USER
+                                 password pre-${NETRC_TEST_TOKEN}-post$$ account $5$
+                               macdef init
+                                 echo $NETRC_TEST_USER
+
+                               default login ${NETRC_TEST_USER}.x");
+        netrc.expand_env(UndefinedVarPolicy::Error).unwrap();
+        let mach = &netrc.hosts[0].1;
+        assert_eq!(mach.login, "bob");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "pre-t0ken-post$");
+        assert_eq!(mach.account.as_ref().unwrap().expose(), "$5$");
+        assert_eq!(netrc.default.unwrap().login, "bob.x");
+        assert!(netrc.macros[0].body.contains("$NETRC_TEST_USER"));
+    }
+
+    #[test]
+    fn expand_env_unset() {
+        std::env::remove_var("NETRC_TEST_UNSET");
+        let input = "machine example.com login foo password ${NETRC_TEST_UNSET}";
+        let mut netrc = parse(input);
+        match netrc.expand_env(UndefinedVarPolicy::Error).unwrap_err() {
+            Error::UndefinedVariable(name) => assert_eq!(name, "NETRC_TEST_UNSET"),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        assert_eq!(netrc.hosts[0].1.password.as_ref().unwrap().expose(), "${NETRC_TEST_UNSET}");
+
+        netrc.expand_env(UndefinedVarPolicy::Keep).unwrap();
+        assert_eq!(netrc.hosts[0].1.password.as_ref().unwrap().expose(), "${NETRC_TEST_UNSET}");
+    }
+
+    #[test]
+    fn expand_env_escape() {
+        std::env::set_var("NETRC_TEST_ESCAPED", "no");
+        let mut netrc = parse("machine example.com login $$NETRC_TEST_ESCAPED
+                                 password $$$NETRC_TEST_ESCAPED account a$$$$b");
+        netrc.expand_env(UndefinedVarPolicy::Error).unwrap();
+        let mach = &netrc.hosts[0].1;
+        assert_eq!(mach.login, "$NETRC_TEST_ESCAPED");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "$no");
+        assert_eq!(mach.account.as_ref().unwrap().expose(), "a$$b");
+
+        let mut mach = parse("machine a.com password ${NETRC_TEST_UNSET_TOO}").hosts.remove(0).1;
+        assert!(mach.expand_env(UndefinedVarPolicy::Error).is_err());
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "${NETRC_TEST_UNSET_TOO}");
+    }
+}
@@ -0,0 +1,203 @@
+//! Parsing into a stream of callbacks instead of a `Netrc`, for scanning
+//! large or untrusted input in constant memory, or for inspecting its raw
+//! structure.
+
+#[cfg(feature = "std")]
+use std::io::BufRead;
+
+use crate::parser::{Event, Parser, Sink};
+#[cfg(feature = "std")]
+use crate::read_line;
+#[cfg(feature = "std")]
+use crate::secret::LineBuffer;
+use crate::{ParseOptions, Result};
+
+/// Receives the pieces of a netrc from `parse`, in input order, each with
+/// the line where it was found.  Every method does nothing by default.
+pub trait NetrcVisitor {
+    /// A `machine` entry starts; the fields until the next entry are its.
+    fn machine(&mut self, _host: &str, _line: usize) {}
+    /// The `default` entry starts.
+    fn default_entry(&mut self, _line: usize) {}
+    fn login(&mut self, _login: &str, _line: usize) {}
+    fn password(&mut self, _password: &str, _line: usize) {}
+    fn account(&mut self, _account: &str, _line: usize) {}
+    fn port(&mut self, _port: u16, _line: usize) {}
+    /// A service name given as the port, with `ParseOptions.port_names`.
+    fn port_name(&mut self, _name: &str, _line: usize) {}
+    fn protocol(&mut self, _protocol: &str, _line: usize) {}
+    /// A field with another keyword, with `ParseOptions.extra_fields`.
+    fn extra(&mut self, _keyword: &str, _value: &str, _line: usize) {}
+    /// A macro named `name` is defined.
+    fn macro_def(&mut self, _name: &str, _line: usize) {}
+    /// A piece of the body of the macro being defined.  Together the
+    /// pieces make up `Macro::body`.
+    fn macro_text(&mut self, _text: &str, _line: usize) {}
+    /// The macro being defined ends.
+    fn macro_end(&mut self, _line: usize) {}
+}
+
+/// A `Sink` forwarding events to a `NetrcVisitor`.
+struct Visiting<'v, V>(&'v mut V);
+
+impl<'l, 'v, V: NetrcVisitor> Sink<'l> for Visiting<'v, V> {
+    fn event(&mut self, event: Event<'l>, line: usize) {
+        let visitor = &mut *self.0;
+        match event {
+            Event::Machine(host)   => visitor.machine(&host, line),
+            Event::Default         => visitor.default_entry(line),
+            Event::Login(v)        => visitor.login(&v, line),
+            Event::Password(v)     => visitor.password(&v, line),
+            Event::Account(v)      => visitor.account(&v, line),
+            Event::Port(port)      => visitor.port(port, line),
+            Event::PortName(v)     => visitor.port_name(&v, line),
+            Event::Protocol(v)     => visitor.protocol(&v, line),
+            Event::Extra(k, v)     => visitor.extra(&k, &v, line),
+            Event::MacroDef(name)  => visitor.macro_def(&name, line),
+            Event::MacroText(text) => visitor.macro_text(text, line),
+            Event::MacroEnd        => visitor.macro_end(line),
+        }
+    }
+}
+
+/// Parse a byte stream, reporting what it holds to `visitor` instead of
+/// building a `Netrc`.  Only one line of input is held at a time.  The
+/// visitor sees everything up to a syntax error, which is then returned.
+///
+/// # Examples
+///
+/// ```
+/// use netrc::events::{self, NetrcVisitor};
+/// use std::io::Cursor;
+///
+/// struct Hosts(Vec<String>);
+///
+/// impl NetrcVisitor for Hosts {
+///     fn machine(&mut self, host: &str, _line: usize) {
+///         self.0.push(host.to_string());
+///     }
+/// }
+///
+/// let input: Cursor<&[u8]> =
+///   Cursor::new(b"machine a.com login a\nmachine b.com login b\n");
+/// let mut hosts = Hosts(Vec::new());
+/// events::parse(input, &mut hosts).unwrap();
+/// assert_eq!(hosts.0, ["a.com", "b.com"]);
+/// ```
+#[cfg(feature = "std")]
+pub fn parse<A: BufRead, V: NetrcVisitor>(buf: A, visitor: &mut V) -> Result<()> {
+    parse_with_options(buf, visitor, &Default::default())
+}
+
+/// Like `parse`, with the given options.  Options that rework the entries
+/// once the whole input is read do not apply, as each entry is reported as
+/// soon as it is read: `on_duplicate_host` at `DuplicateHost::KeepLast` or
+/// `DuplicateHost::Merge` reports every entry as `DuplicateHost::KeepAll`
+/// does, and `infer_port_from_protocol` reports no inferred port.
+#[cfg(feature = "std")]
+pub fn parse_with_options<A, V>(mut buf: A,
+                                visitor: &mut V,
+                                options: &ParseOptions) -> Result<()>
+    where A: BufRead,
+          V: NetrcVisitor
+{
+    let mut parser = Parser::new(options, Visiting(visitor));
+    let mut line = LineBuffer::for_lines(options.max_line_length, options.encoding);
+    while read_line(&mut buf, &mut line, options.max_line_length, options.encoding)? > 0 {
+        parser.feed_line(&line)?;
+        line.clear();
+    }
+    parser.finish()?;
+    Ok(())
+}
+
+/// Like `parse_with_options`, for a netrc held in memory as text, and with
+/// the same options left out.  Unlike the parsers reading a byte stream,
+/// this is available without the `std` feature.
+pub fn parse_str<V: NetrcVisitor>(input: &str, visitor: &mut V, options: &ParseOptions) -> Result<()> {
+    let mut parser = Parser::new(options, Visiting(visitor));
+    for line in input.split_inclusive('\n') {
+        parser.feed_line(line)?;
+    }
+    parser.finish()?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::{parse, NetrcVisitor};
+    use crate::{Error, Netrc};
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl NetrcVisitor for Recorder {
+        fn machine(&mut self, host: &str, line: usize) {
+            self.0.push(format!("{} machine {}", line, host));
+        }
+        fn default_entry(&mut self, line: usize) {
+            self.0.push(format!("{} default", line));
+        }
+        fn login(&mut self, login: &str, line: usize) {
+            self.0.push(format!("{} login {}", line, login));
+        }
+        fn password(&mut self, password: &str, line: usize) {
+            self.0.push(format!("{} password {}", line, password));
+        }
+        fn port(&mut self, port: u16, line: usize) {
+            self.0.push(format!("{} port {}", line, port));
+        }
+        fn macro_def(&mut self, name: &str, line: usize) {
+            self.0.push(format!("{} macdef {}", line, name));
+        }
+        fn macro_end(&mut self, line: usize) {
+            self.0.push(format!("{} end", line));
+        }
+    }
+
+    #[test]
+    fn parse_events() {
+        let input = "machine a.com login a
+                     password p port 21
+                     macdef init
+                     bin
+
+                     default login def";
+        let mut recorder = Recorder::default();
+        parse(input.as_bytes(), &mut recorder).unwrap();
+        assert_eq!(recorder.0, ["1 machine a.com", "1 login a", "2 password p",
+                                "2 port 21", "3 macdef init", "5 end",
+                                "6 default", "6 login def"]);
+    }
+
+    #[test]
+    fn parse_events_macro_text() {
+        struct Body(String);
+
+        impl NetrcVisitor for Body {
+            fn macro_text(&mut self, text: &str, _line: usize) {
+                self.0.push_str(text);
+            }
+        }
+
+        let input = "macdef init\ncd /pub\nbin\n";
+        let mut body = Body(String::new());
+        parse(input.as_bytes(), &mut body).unwrap();
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(body.0, netrc.macros[0].body);
+    }
+
+    #[test]
+    fn parse_events_error() {
+        let mut recorder = Recorder::default();
+        let input = "machine a.com login a\nfoo";
+        match parse(input.as_bytes(), &mut recorder).unwrap_err() {
+            Error::UnknownEntry { keyword, line } => {
+                assert_eq!(keyword, "foo");
+                assert_eq!(line, 2);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        assert_eq!(recorder.0, ["1 machine a.com", "1 login a"]);
+    }
+}
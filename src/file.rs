@@ -0,0 +1,701 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+#[cfg(feature = "gpg")]
+use std::io::{BufRead, Cursor};
+use std::path::{Path, PathBuf};
+
+use crate::parser::{Builder, Parser};
+use crate::document::Document;
+use crate::secret::{LineBuffer, SecretReader};
+use crate::{read_line, Error, Machine, Netrc, ParseOptions, Result};
+
+/// `path` with `suffix` appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Options controlling where to look for the user's netrc file.  See
+/// `Netrc::from_default_location_with_options`.
+#[derive(Clone, Debug, Default)]
+pub struct LocationOptions {
+    /// Look for `netrc` in `$XDG_CONFIG_HOME`, or in `.config` in the home
+    /// directory if it is not set, before the usual places.
+    pub xdg: bool,
+}
+
+/// Where to look for the user's netrc file, in order: `$NETRC` alone if
+/// it is set, else `.netrc` in `$HOME`, or on Windows `_netrc` in
+/// `%USERPROFILE%` and then in `%HOME%`.  With `options.xdg`, `netrc` in
+/// the XDG config directory comes first.  With the `gpg` feature, each
+/// file name is followed by itself with `.gpg` appended.
+fn default_locations(options: &LocationOptions) -> Vec<PathBuf> {
+    locations(options, |name| env::var_os(name))
+}
+
+/// `default_locations` with `var` giving the value of each environment
+/// variable.
+fn locations<F>(options: &LocationOptions, var: F) -> Vec<PathBuf>
+    where F: Fn(&str) -> Option<OsString>
+{
+    if let Some(path) = var("NETRC") {
+        return vec![PathBuf::from(path)];
+    }
+    #[cfg(windows)]
+    let (vars, name) = (["USERPROFILE", "HOME"], "_netrc");
+    #[cfg(not(windows))]
+    let (vars, name) = (["HOME"], ".netrc");
+    let mut files: Vec<(PathBuf, &str)> = Vec::new();
+    if options.xdg {
+        let config = var("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| vars.iter().find_map(|&v| var(v)).map(|home| Path::new(&home).join(".config")));
+        files.extend(config.map(|dir| (dir, "netrc")));
+    }
+    files.extend(vars.iter().filter_map(|&v| var(v)).map(|dir| (PathBuf::from(dir), name)));
+    let mut paths = Vec::new();
+    for (dir, name) in files {
+        paths.push(dir.join(name));
+        #[cfg(feature = "gpg")]
+        paths.push(dir.join(format!("{}.gpg", name)));
+    }
+    paths
+}
+
+/// Parse the first of `paths` that is a file, returning it with its path.
+fn read_first(paths: Vec<PathBuf>) -> Result<(Netrc, PathBuf)> {
+    match paths.iter().find(|path| path.is_file()) {
+        Some(path) => Ok((Netrc::from_file(path)?, path.clone())),
+        None       => Err(Error::NoNetrcFile(paths)),
+    }
+}
+
+/// Where the user's netrc file is, found as by `Netrc::from_default_location`,
+/// or else where it would be created: the first place looked at.  `None` if
+/// there is nowhere to look, as without a home directory.
+///
+/// Only available with the `fs` feature, which is on by default.
+pub fn default_path() -> Option<PathBuf> {
+    let paths = default_locations(&Default::default());
+    paths.iter().find(|path| path.is_file()).or_else(|| paths.first()).cloned()
+}
+
+/// The effective user ID of this process.
+#[cfg(unix)]
+fn effective_uid() -> u32 {
+    extern "C" {
+        fn geteuid() -> u32;
+    }
+    // SAFETY: `geteuid` has no preconditions and always succeeds.
+    unsafe { geteuid() }
+}
+
+/// Fail unless the file at `path` is owned by the current user and not
+/// accessible to anyone else, as ftp requires of a netrc with passwords.
+#[cfg(unix)]
+fn check_private(path: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::metadata(path)?;
+    let msg = if meta.mode() & 0o077 != 0 {
+        format!("accessible by other users (mode {:o})", meta.mode() & 0o777)
+    } else if meta.uid() != effective_uid() {
+        "not owned by the current user".to_string()
+    } else {
+        return Ok(());
+    };
+    Err(Error::InsecurePermissions { path: path.to_path_buf(), msg })
+}
+
+/// The port of the service `name` in `/etc/services`, which is read once.
+#[cfg(unix)]
+pub(crate) fn system_service_port(name: &str) -> Option<u16> {
+    use std::collections::HashMap;
+    use std::sync::OnceLock;
+
+    static SERVICES: OnceLock<HashMap<String, u16>> = OnceLock::new();
+    let services = SERVICES.get_or_init(|| {
+        let text = fs::read_to_string("/etc/services").unwrap_or_default();
+        let mut services = HashMap::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            let (name, port) = match (words.next(), words.next()) {
+                (Some(name), Some(port)) => (name, port),
+                _                        => continue,
+            };
+            let port = match port.split('/').next().and_then(|p| p.parse().ok()) {
+                Some(port) => port,
+                None       => continue,
+            };
+            for name in std::iter::once(name).chain(words) {
+                services.entry(name.to_ascii_lowercase()).or_insert(port);
+            }
+        }
+        services
+    });
+    services.get(name).copied()
+}
+
+/// Create or truncate `path` for writing, readable by its owner only on
+/// Unix, including when the file already exists.
+fn create_private(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        let file = options.open(path)?;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    options.open(path)
+}
+
+/// The path of an `include` directive making up `line`, if there is one.
+fn include_target<'l>(parser: &Parser<Builder>, line: &'l str) -> Result<Option<&'l str>> {
+    if !parser.options.includes || !parser.expects_entry() {
+        return Ok(None);
+    }
+    let mut words = line.split_whitespace();
+    if words.next() != Some("include") {
+        return Ok(None);
+    }
+    match (words.next(), words.next()) {
+        (Some(path), None) => Ok(Some(path)),
+        _                  => {
+            let msg = "Expected a single path after `include'".to_string();
+            Err(Error::Parse { msg, line: parser.lnum })
+        }
+    }
+}
+
+/// Feed the lines of the file at `path` to `parser`, following `include`
+/// directives.  `stack` holds the files being read, outermost first.
+fn feed_file(parser: &mut Parser<Builder>,
+             path: &Path,
+             stack: &mut Vec<PathBuf>) -> Result<()> {
+    #[cfg(feature = "gpg")]
+    let mut buf: Box<dyn BufRead> = match crate::gpg::decrypt_if_encrypted(path)? {
+        Some(plain) => Box::new(Cursor::new(plain)),
+        None        => Box::new(SecretReader::new(File::open(path)?)),
+    };
+    #[cfg(not(feature = "gpg"))]
+    let mut buf = SecretReader::new(File::open(path)?);
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let home = home_dir();
+    let max = parser.options.max_line_length;
+    let mut line = LineBuffer::for_lines(max, parser.options.encoding);
+    while read_line(&mut buf, &mut line, max, parser.options.encoding)? > 0 {
+        match include_target(parser, &line)? {
+            Some(target) => {
+                parser.lnum += 1;
+                include(parser, &include_path(dir, target, home.as_deref()), stack)?;
+            }
+            None => parser.feed_line(&line)?,
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+/// The home directory, as `~` stands for in an `include` directive.
+fn home_dir() -> Option<PathBuf> {
+    #[cfg(windows)]
+    let vars = ["USERPROFILE", "HOME"];
+    #[cfg(not(windows))]
+    let vars = ["HOME"];
+    vars.iter().filter_map(env::var_os).next().map(PathBuf::from)
+}
+
+/// The file named by `target` in an `include` directive of a file in
+/// `dir`: relative to `dir`, or to `home` if it starts with `~/`.
+fn include_path(dir: &Path, target: &str, home: Option<&Path>) -> PathBuf {
+    let home_relative = match target {
+        "~" => Some(""),
+        _   => target.strip_prefix("~/"),
+    };
+    match (home_relative, home) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _                        => dir.join(target),
+    }
+}
+
+/// Read the file at `path` from an `include` directive on the current
+/// line of `parser`, whose line numbers then count from the start of that
+/// file until it ends.
+fn include(parser: &mut Parser<Builder>,
+           path: &Path,
+           stack: &mut Vec<PathBuf>) -> Result<()> {
+    let canonical = match fs::canonicalize(path) {
+        Ok(canonical) => canonical,
+        Err(e)        => {
+            let msg = format!("Cannot include `{}': {}", path.display(), e);
+            return Err(Error::Parse { msg, line: parser.lnum });
+        }
+    };
+    if stack.contains(&canonical) {
+        let msg = format!("Include cycle through `{}'", path.display());
+        return Err(Error::Parse { msg, line: parser.lnum });
+    }
+    let max_depth = parser.options.max_include_depth;
+    if stack.len() > max_depth {
+        let msg = format!("Includes nested deeper than {}", max_depth);
+        return Err(Error::Parse { msg, line: parser.lnum });
+    }
+    let lnum = parser.lnum;
+    parser.lnum = 0;
+    stack.push(canonical);
+    feed_file(parser, path, stack)?;
+    stack.pop();
+    parser.lnum = lnum;
+    Ok(())
+}
+
+impl Netrc {
+    /// Parse the netrc file at `path`.
+    ///
+    /// With the `gpg` feature, a file named with a `.gpg` extension, or
+    /// starting like an OpenPGP message, is first decrypted by running
+    /// `gpg --decrypt`, which may ask for a passphrase.  This holds for
+    /// included files as well.
+    ///
+    /// Only available with the `fs` feature, which is on by default.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Netrc> {
+        Netrc::from_file_with_options(path, &Default::default())
+    }
+
+    /// Parse the netrc file at `path` with the given options.
+    ///
+    /// With `options.includes`, a line `include PATH` where an entry may
+    /// start is replaced by the contents of the file at `PATH`, relative to
+    /// the directory of the including file, or to the home directory if it
+    /// starts with `~/`.  Errors in an included file have line numbers
+    /// counting from its start.  Including a file that is missing, that is
+    /// already being read, or beyond `options.max_include_depth` is an
+    /// error at the line of the `include`.
+    ///
+    /// Only available with the `fs` feature, which is on by default.
+    pub fn from_file_with_options<P: AsRef<Path>>(path: P,
+                                                  options: &ParseOptions) -> Result<Netrc> {
+        let mut parser = Parser::new(options, Builder::new());
+        let path = path.as_ref();
+        let mut stack = vec![fs::canonicalize(path)?];
+        feed_file(&mut parser, path, &mut stack)?;
+        parser.finish_netrc()
+    }
+
+    /// Parse the netrc file at `path` like `from_file`, but if it has any
+    /// password, fail with `Error::InsecurePermissions` unless on Unix the
+    /// file is owned by the current user and inaccessible to group and
+    /// others.  No check is made on other platforms.
+    ///
+    /// Only available with the `fs` feature, which is on by default.
+    pub fn from_file_secure<P: AsRef<Path>>(path: P) -> Result<Netrc> {
+        let path = path.as_ref();
+        let netrc = Netrc::from_file(path)?;
+        let has_password = netrc.hosts.iter().map(|(_, mach)| mach)
+            .chain(&netrc.default)
+            .any(|mach| mach.password.is_some());
+        #[cfg(unix)]
+        {
+            if has_password {
+                check_private(path)?;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = has_password;
+        Ok(netrc)
+    }
+
+    /// Parse the user's netrc file, found as by curl: the file named by the
+    /// `NETRC` environment variable if set, else `.netrc` in the home
+    /// directory, or `_netrc` under `USERPROFILE` or `HOME` on Windows.
+    /// Fails with `Error::NoNetrcFile` if there is no such file.
+    ///
+    /// Only available with the `fs` feature, which is on by default.
+    pub fn from_default_location() -> Result<Netrc> {
+        Netrc::from_default_location_with_options(&Default::default()).map(|(netrc, _)| netrc)
+    }
+
+    /// Parse the user's netrc file like `from_default_location`, looking
+    /// for it as `options` say, and return it with the path it was read
+    /// from, for telling the user where credentials came from.
+    ///
+    /// Only available with the `fs` feature, which is on by default.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use netrc::{LocationOptions, Netrc};
+    ///
+    /// let options = LocationOptions { xdg: true };
+    /// let (netrc, path) = Netrc::from_default_location_with_options(&options).unwrap();
+    /// println!("Read {} entries from {}", netrc.hosts.len(), path.display());
+    /// ```
+    pub fn from_default_location_with_options(options: &LocationOptions)
+                                              -> Result<(Netrc, PathBuf)> {
+        read_first(default_locations(options))
+    }
+
+    /// Serialize to the file at `path`, creating or truncating it.  On Unix
+    /// the file is made readable and writable by its owner only, even if
+    /// it already existed with wider permissions.  See `save_to_file` to
+    /// replace an existing file atomically.
+    ///
+    /// Only available with the `fs` feature, which is on by default.
+    pub fn write_to_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = create_private(path.as_ref())?;
+        file.write_all(self.to_string().as_bytes())?;
+        Ok(())
+    }
+
+    /// Serialize to the file at `path`, replacing it atomically and
+    /// without a backup, as by `save_to_file`.
+    ///
+    /// Only available with the `fs` feature, which is on by default.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.save_to_file(path, false)
+    }
+
+    /// Serialize to the file at `path`, replacing it atomically: the
+    /// contents are written to `path` with `.tmp` appended, which is then
+    /// renamed over `path`.  On Unix the file is only readable and writable
+    /// by its owner.  With `keep_backup`, a file already at `path` is first
+    /// copied to `path` with `.bak` appended.
+    ///
+    /// Only available with the `fs` feature, which is on by default.
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P, keep_backup: bool) -> Result<()> {
+        replace_file(path.as_ref(), self.to_string().as_bytes(), keep_backup)?;
+        Ok(())
+    }
+}
+
+impl Document {
+    /// Write the text to the file at `path`, replacing it atomically as by
+    /// `Netrc::save_to_file`.
+    ///
+    /// Only available with the `fs` feature, which is on by default.
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P, keep_backup: bool) -> Result<()> {
+        replace_file(path.as_ref(), self.to_string().as_bytes(), keep_backup)?;
+        Ok(())
+    }
+}
+
+/// Replace the file at `path` with `contents` through a private `.tmp`
+/// file renamed over it, first copying it to `.bak` with `keep_backup`.
+fn replace_file(path: &Path, contents: &[u8], keep_backup: bool) -> std::io::Result<()> {
+    let tmp = with_suffix(path, ".tmp");
+    let result = (|| {
+        let mut file = create_private(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        if keep_backup && path.exists() {
+            fs::copy(path, with_suffix(path, ".bak"))?;
+        }
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Append a `machine` entry for `host` to the netrc file at `path`,
+/// leaving the text already there untouched, or create the file with just
+/// that entry.  A line break is added first if the file does not end with
+/// one, and a blank line if it ends in a macro definition.  Fails without
+/// writing if the file does not parse as by `Document::parse`.
+///
+/// The file is replaced atomically as by `Netrc::save_to_file`, so on Unix
+/// it is only readable and writable by its owner afterwards.
+///
+/// Only available with the `fs` feature, which is on by default.
+///
+/// # Examples
+///
+/// ```no_run
+/// use netrc::Machine;
+///
+/// let mach = Machine::builder().login("ci").password("token").build();
+/// netrc::append_machine("/home/ci/.netrc", "registry.example.com", &mach).unwrap();
+/// ```
+pub fn append_machine<P: AsRef<Path>>(path: P, host: &str, machine: &Machine) -> Result<()> {
+    let path = path.as_ref();
+    let mut doc = match fs::read_to_string(path) {
+        Ok(text)                                          => Document::parse(&text)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Document::default(),
+        Err(e)                                            => return Err(e.into()),
+    };
+    doc.push_machine(host, machine);
+    replace_file(path, doc.to_string().as_bytes(), false)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{append_machine, include_path, locations, read_first, LocationOptions};
+    use std::ffi::OsString;
+    use std::path::Path;
+    use crate::{Error, Machine, Netrc, ParseOptions};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir();
+        dir.join(format!("netrc-test-{}-{}", std::process::id(), name))
+    }
+
+    /// Environment variables for `locations`, without touching the real
+    /// environment other tests may read.
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        move |name| vars.iter().find(|(n, _)| *n == name).map(|(_, v)| OsString::from(v))
+    }
+
+    #[test]
+    fn save_to_file_round_trip() {
+        let path = temp_path("save");
+        let bak = temp_path("save.bak");
+        fs::write(&path, "machine old.com login old\n").unwrap();
+        let input = "machine example.com login test password p@ss
+                     default login def";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        netrc.save_to_file(&path, true).unwrap();
+
+        let saved = Netrc::from_file(&path).unwrap();
+        assert_eq!(saved.to_string(), netrc.to_string());
+        assert_eq!(Netrc::from_file(&bak).unwrap().hosts[0].0, "old.com");
+        assert!(!temp_path("save.tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&bak).unwrap();
+    }
+
+    #[test]
+    fn save_replaces_file() {
+        let path = temp_path("save-plain");
+        fs::write(&path, "machine old.com login old\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+        let netrc = Netrc::parse("machine example.com login test".as_bytes()).unwrap();
+        netrc.save(&path).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "machine example.com login test\n");
+        assert!(!temp_path("save-plain.tmp").exists());
+        assert!(!temp_path("save-plain.bak").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_to_path_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path("write");
+        fs::write(&path, "machine old.com login old password long-enough-to-truncate\n")
+            .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let netrc = Netrc::parse("machine example.com login test".as_bytes()).unwrap();
+        netrc.write_to_path(&path).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "machine example.com login test\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_file_secure() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path("secure");
+        let set_mode = |mode| {
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        };
+        fs::write(&path, "machine example.com login test\n").unwrap();
+        set_mode(0o644);
+        assert!(Netrc::from_file_secure(&path).is_ok());
+
+        fs::write(&path, "machine example.com login test\ndefault password p\n").unwrap();
+        match Netrc::from_file_secure(&path).unwrap_err() {
+            Error::InsecurePermissions { path: p, msg } => {
+                assert_eq!(p, path);
+                assert_eq!(msg, "accessible by other users (mode 644)");
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        set_mode(0o600);
+        assert_eq!(Netrc::from_file_secure(&path).unwrap().hosts[0].0, "example.com");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn from_default_location_netrc_var() {
+        let path = temp_path("default-location");
+        fs::write(&path, "machine example.com login test\n").unwrap();
+        let vars = [("NETRC", path.to_str().unwrap())];
+        let find = || read_first(locations(&Default::default(), env(&vars)));
+        let (netrc, found) = find().unwrap();
+        assert_eq!(netrc.hosts[0].0, "example.com");
+        assert_eq!(found, path);
+
+        fs::remove_file(&path).unwrap();
+        match find().unwrap_err() {
+            Error::NoNetrcFile(paths) => assert_eq!(paths, [path]),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn default_locations_xdg() {
+        let names = |paths: Vec<PathBuf>| -> Vec<String> {
+            paths.iter()
+                .map(|p| p.to_str().unwrap().to_string())
+                .filter(|p| !p.ends_with(".gpg"))
+                .collect()
+        };
+        let xdg = LocationOptions { xdg: true };
+        let home = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+        let netrc = if cfg!(windows) { "_netrc" } else { ".netrc" };
+        let vars = [(home, "/h"), ("XDG_CONFIG_HOME", "/x")];
+        assert_eq!(names(locations(&Default::default(), env(&vars))),
+                   [Path::new("/h").join(netrc).to_str().unwrap()]);
+        assert_eq!(names(locations(&xdg, env(&vars))),
+                   [Path::new("/x").join("netrc").to_str().unwrap(),
+                    Path::new("/h").join(netrc).to_str().unwrap()]);
+        let vars = [(home, "/h")];
+        assert_eq!(names(locations(&xdg, env(&vars)))[0],
+                   Path::new("/h").join(".config").join("netrc").to_str().unwrap());
+        let vars = [(home, "/h"), ("NETRC", "/n")];
+        assert_eq!(names(locations(&xdg, env(&vars))), ["/n"]);
+    }
+
+    #[test]
+    fn append_machine_to_file() {
+        let path = temp_path("append");
+        let mach = Machine::builder().login("ci").password("secret token").build();
+        let _ = fs::remove_file(&path);
+        append_machine(&path, "a.com", &mach).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(),
+                   "machine a.com login ci password \"secret token\"\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::write(&path, "# mine\nmacdef init\nbin").unwrap();
+        append_machine(&path, "b.com", &Machine::builder().login("b").build()).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(text, "# mine\nmacdef init\nbin\n\nmachine b.com login b\n");
+        let options = ParseOptions { comments: true, ..Default::default() };
+        let netrc = Netrc::from_file_with_options(&path, &options).unwrap();
+        assert_eq!(netrc.hosts[0].0, "b.com");
+        assert_eq!(netrc.macros[0].body_trimmed(), "bin");
+
+        fs::write(&path, "machine a.com\nfoo\n").unwrap();
+        assert!(append_machine(&path, "b.com", &mach).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "machine a.com\nfoo\n");
+        assert!(!temp_path("append.tmp").exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn from_file_includes() {
+        let main = temp_path("include-main");
+        let other = temp_path("include-other");
+        let last = temp_path("include-last");
+        fs::write(&main, format!("include {}\nmachine main.com login m\n",
+                                 other.file_name().unwrap().to_str().unwrap())).unwrap();
+        fs::write(&other, format!("machine other.com login o\ninclude {}\n",
+                                  last.display())).unwrap();
+        fs::write(&last, "default login def\n").unwrap();
+        let options = ParseOptions { includes: true, ..Default::default() };
+        let netrc = Netrc::from_file_with_options(&main, &options).unwrap();
+        let names: Vec<_> = netrc.hosts.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["other.com", "main.com"]);
+        assert_eq!(netrc.default.unwrap().login, "def");
+        assert!(Netrc::from_file(&main).is_err());
+        for path in &[main, other, last] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn include_paths() {
+        let dir = Path::new("/etc");
+        let home = Path::new("/home/me");
+        assert_eq!(include_path(dir, "~/.netrc.d/registry", Some(home)),
+                   Path::new("/home/me/.netrc.d/registry"));
+        assert_eq!(include_path(dir, "~", Some(home)), home);
+        assert_eq!(include_path(dir, "~/x", None), Path::new("/etc/~/x"));
+        assert_eq!(include_path(dir, "~other/x", Some(home)), Path::new("/etc/~other/x"));
+        assert_eq!(include_path(dir, "netrc.d/a", Some(home)), Path::new("/etc/netrc.d/a"));
+    }
+
+    #[test]
+    fn from_file_include_errors() {
+        let main = temp_path("include-depth-main");
+        let other = temp_path("include-depth-other");
+        fs::write(&main, format!("machine a.com\ninclude {}\n", other.display())).unwrap();
+        fs::write(&other, "machine b.com\n").unwrap();
+        let options = ParseOptions { includes: true, max_include_depth: 0, ..Default::default() };
+        match Netrc::from_file_with_options(&main, &options).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Includes nested deeper than 0");
+                assert_eq!(line, 2);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        let options = ParseOptions { max_include_depth: 1, ..options };
+        assert_eq!(Netrc::from_file_with_options(&main, &options).unwrap().hosts.len(), 2);
+
+        fs::remove_file(&other).unwrap();
+        match Netrc::from_file_with_options(&main, &options).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert!(msg.starts_with("Cannot include"), "{}", msg);
+                assert_eq!(line, 2);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        fs::remove_file(&main).unwrap();
+    }
+
+    #[test]
+    fn from_file_include_cycle() {
+        let path = temp_path("include-self");
+        fs::write(&path, format!("machine a.com login a\ninclude {}\n",
+                                 path.display())).unwrap();
+        let options = ParseOptions { includes: true, ..Default::default() };
+        match Netrc::from_file_with_options(&path, &options).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert!(msg.starts_with("Include cycle"), "{}", msg);
+                assert_eq!(line, 2);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
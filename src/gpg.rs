@@ -0,0 +1,83 @@
+//! Reading netrc files encrypted with OpenPGP, such as `~/.netrc.gpg`,
+//! through the `gpg` command.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::secret::SecretBytes;
+
+/// How an ASCII-armored OpenPGP message starts.
+const ARMOR: &[u8] = b"-----BEGIN PGP MESSAGE-----";
+
+/// Whether `head`, the start of a file, looks like an OpenPGP message: an
+/// armor header, or a packet tag for an encrypted session key, compressed
+/// data, literal data or encrypted data.
+fn is_openpgp(head: &[u8]) -> bool {
+    if head.starts_with(ARMOR) {
+        return true;
+    }
+    let tag = match head.first() {
+        Some(&b) if b & 0xc0 == 0xc0 => b & 0x3f,
+        Some(&b) if b & 0x80 != 0    => (b >> 2) & 0x0f,
+        _                            => return false,
+    };
+    matches!(tag, 1 | 3 | 8 | 11 | 18 | 20)
+}
+
+/// The plaintext of the file at `path` if it is named `*.gpg` or holds an
+/// OpenPGP message, decrypted by `gpg`, which may ask for a passphrase.
+pub(crate) fn decrypt_if_encrypted(path: &Path) -> io::Result<Option<SecretBytes>> {
+    let named_gpg = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gpg"));
+    if !named_gpg {
+        let mut head = [0; ARMOR.len()];
+        let mut file = File::open(path)?;
+        let mut len = 0;
+        while len < head.len() {
+            match file.read(&mut head[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        if !is_openpgp(&head[..len]) {
+            return Ok(None);
+        }
+    }
+    let mut child = Command::new("gpg")
+        .args(["--quiet", "--decrypt", "--"])
+        .arg(path)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Read the plaintext ourselves rather than with `output`, so that it
+    // is wiped with the `secure` feature.  With `--quiet` there is too
+    // little on standard error for it to fill up meanwhile.
+    let mut plain = SecretBytes::default();
+    plain.read_from(child.stdout.take().expect("piped stdout"))?;
+    let mut msg = String::new();
+    child.stderr.take().expect("piped stderr").read_to_string(&mut msg)?;
+    if !child.wait()?.success() {
+        return Err(io::Error::new(io::ErrorKind::Other,
+                                  format!("gpg failed to decrypt `{}': {}",
+                                          path.display(), msg.trim())));
+    }
+    Ok(Some(plain))
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_openpgp;
+
+    #[test]
+    fn detect_openpgp() {
+        assert!(is_openpgp(b"-----BEGIN PGP MESSAGE-----\n"));
+        assert!(is_openpgp(&[0x85, 0x01, 0x0c]));
+        assert!(is_openpgp(&[0xc1, 0x0c]));
+        assert!(is_openpgp(&[0x8c, 0x0d]));
+        assert!(!is_openpgp(b"machine example.com"));
+        assert!(!is_openpgp(b"\xef\xbb\xbfmachine example.com"));
+        assert!(!is_openpgp(b""));
+    }
+}
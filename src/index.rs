@@ -0,0 +1,112 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::{Machine, Netrc};
+
+/// Lookups into a `Netrc` in constant time, for looking up many hosts.
+/// See `Netrc::index`.
+#[derive(Clone, Debug, Default)]
+pub struct NetrcIndex<'a> {
+    hosts: HashMap<String, HostEntries<'a>>,
+    default: Option<&'a Machine>,
+}
+
+/// The entries for one host.
+#[derive(Clone, Debug)]
+struct HostEntries<'a> {
+    first: &'a Machine,
+    /// The first entry without a port.
+    any_port: Option<&'a Machine>,
+    /// The first entry for each port.
+    ports: HashMap<u16, &'a Machine>,
+}
+
+/// `host` as compared by `same_host`.
+fn key(host: &str) -> Cow<'_, str> {
+    if host.bytes().any(|b| b.is_ascii_uppercase()) {
+        Cow::Owned(host.to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(host)
+    }
+}
+
+impl<'a> NetrcIndex<'a> {
+    /// The first `machine` entry for `host`, else the `default` entry, as
+    /// by `Netrc::find_machine`.
+    pub fn get(&self, host: &str) -> Option<&'a Machine> {
+        self.hosts.get(key(host).as_ref()).map(|entries| entries.first).or(self.default)
+    }
+
+    /// The first `machine` entry for `host` on `port`, else the first for
+    /// `host` without a port, as by `Netrc::get_host_port`, else the
+    /// `default` entry.
+    pub fn get_with_port(&self, host: &str, port: u16) -> Option<&'a Machine> {
+        self.hosts.get(key(host).as_ref())
+            .and_then(|entries| entries.ports.get(&port).copied().or(entries.any_port))
+            .or(self.default)
+    }
+}
+
+impl Netrc {
+    /// Index the entries by host, and by host and port, for looking up
+    /// many hosts without scanning every entry each time.  The index
+    /// borrows `self`, so it has to be rebuilt after a change.
+    ///
+    /// Only available with the `std` feature, which is on by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let input = "machine a.com login a port 21 machine a.com login b default login anon";
+    /// let netrc = Netrc::parse(input.as_bytes()).unwrap();
+    /// let index = netrc.index();
+    /// assert_eq!(index.get("A.com").unwrap().login, "a");
+    /// assert_eq!(index.get_with_port("a.com", 22).unwrap().login, "b");
+    /// assert_eq!(index.get("b.com").unwrap().login, "anon");
+    /// ```
+    pub fn index(&self) -> NetrcIndex<'_> {
+        let mut index = NetrcIndex { default: self.default.as_ref(), ..Default::default() };
+        for (host, mach) in &self.hosts {
+            let entries = index.hosts.entry(key(host).into_owned()).or_insert_with(|| {
+                HostEntries { first: mach, any_port: None, ports: HashMap::new() }
+            });
+            match mach.port {
+                Some(port) => { entries.ports.entry(port).or_insert(mach); }
+                None       => { entries.any_port.get_or_insert(mach); }
+            }
+        }
+        index
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Netrc;
+
+    #[test]
+    fn index_matches_scans() {
+        let input = "machine a.com login a1 port 21
+                     machine A.COM login a2
+                     machine a.com login a3 port 22
+                     machine a.com login a4 port 21
+                     machine b.com login b port 21";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let index = netrc.index();
+        for host in ["a.com", "A.Com", "b.com", "c.com"] {
+            assert_eq!(index.get(host).map(|m| &m.login), netrc.get(host).map(|m| &m.login));
+            for port in [21, 22, 23] {
+                assert_eq!(index.get_with_port(host, port).map(|m| &m.login),
+                           netrc.get_host_port(host, port).map(|m| &m.login));
+            }
+        }
+
+        let input = format!("{} default login anon", input);
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let index = netrc.index();
+        assert_eq!(index.get("c.com").unwrap().login, "anon");
+        assert_eq!(index.get_with_port("b.com", 22).unwrap().login, "anon");
+        assert_eq!(index.get_with_port("a.com", 23).unwrap().login, "a2");
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{Machine, Netrc};
+
+const REDACTED: &str = "***";
+
+fn redact(mach: &mut Machine) {
+    if mach.password.is_some() {
+        mach.password = Some(REDACTED.into());
+    }
+    if mach.account.is_some() {
+        mach.account = Some(REDACTED.into());
+    }
+}
+
+impl Netrc {
+    /// Render as indented JSON with every password and account replaced
+    /// by `"***"`, for logging the structure without leaking secrets.
+    ///
+    /// Only available with the `serde` feature.
+    pub fn to_pretty_json_redacted(&self) -> String {
+        let mut netrc = self.clone();
+        for (_, mach) in &mut netrc.hosts {
+            redact(mach);
+        }
+        if let Some(ref mut mach) = netrc.default {
+            redact(mach);
+        }
+        serde_json::to_string_pretty(&netrc).expect("Netrc is always valid JSON")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Netrc;
+    use std::io::BufReader;
+
+    #[test]
+    fn pretty_json_redacted() {
+        let input = "machine example.com login test password s3cret port 42
+                     default login anon account acct0 password guest";
+        let netrc = Netrc::parse(BufReader::new(input.as_bytes())).unwrap();
+        let json = netrc.to_pretty_json_redacted();
+        for secret in &["s3cret", "acct0", "guest"] {
+            assert!(!json.contains(secret), "{} leaked: {}", secret, json);
+        }
+        assert!(json.contains("\"example.com\""));
+        assert!(json.contains("\"login\": \"test\""));
+        assert!(json.contains("\"password\": \"***\""));
+        assert!(json.contains("\"port\": 42"));
+        assert!(json.contains('\n'));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let input = "machine example.com login test password s3cret port 42
+                     default login anon
+                     macdef init
+                     bin
+";
+        let netrc = Netrc::parse(BufReader::new(input.as_bytes())).unwrap();
+        let json = serde_json::to_string(&netrc).unwrap();
+        let back: Netrc = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.to_string(), netrc.to_string());
+        assert_eq!(back.hosts[0].1.line, Some(1));
+
+        let json = serde_json::to_string(&netrc.redacted()).unwrap();
+        assert!(!json.contains("password"), "{}", json);
+        let back: Netrc = serde_json::from_str(&json).unwrap();
+        assert!(back.hosts[0].1.password.is_none());
+
+        let back: Netrc = serde_json::from_str(r#"{"hosts": [["a.com", {"login": "a"}]]}"#)
+            .unwrap();
+        assert_eq!(back.hosts[0].1.login, "a");
+        assert!(back.default.is_none());
+    }
+}
@@ -0,0 +1,372 @@
+//! Passwords kept in the OS secret store, written in a netrc as
+//! `@keyring:SERVICE/ACCOUNT`.
+
+use std::io;
+#[cfg(not(windows))]
+use std::io::Write;
+#[cfg(not(windows))]
+use std::process::{Command, Stdio};
+
+use crate::{Error, Machine, Netrc, Result, Secret};
+
+/// How a password referring to a keyring entry starts.
+const PREFIX: &str = "@keyring:";
+
+/// A store of secrets, each found by a service and an account name.
+pub trait SecretStore {
+    /// The secret for `account` of `service`, or `None` if there is none.
+    fn get(&self, service: &str, account: &str) -> io::Result<Option<String>>;
+    /// Store `secret` for `account` of `service`, replacing any already
+    /// there.
+    fn set(&self, service: &str, account: &str, secret: &str) -> io::Result<()>;
+}
+
+/// The secret store of the OS: the Credential Manager on Windows, and
+/// otherwise through its command line tools, the Keychain with `security`
+/// on macOS and the Secret Service with `secret-tool` from libsecret
+/// elsewhere.  Secrets are never passed to a tool as arguments, which
+/// other users could read from the process list.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemKeyring;
+
+/// Run `command`, feeding it `input`, and return its standard output, or
+/// `None` if it failed.
+#[cfg(not(windows))]
+fn run(command: &mut Command, input: Option<&str>) -> io::Result<Option<String>> {
+    let mut child = command.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(input) = input {
+        child.stdin.take().expect("piped stdin").write_all(input.as_bytes())?;
+    }
+    drop(child.stdin.take());
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    String::from_utf8(output.stdout)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// `arg` quoted for a command line of `security -i`.
+#[cfg(not(windows))]
+fn security_quote(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl SecretStore for SystemKeyring {
+    fn get(&self, service: &str, account: &str) -> io::Result<Option<String>> {
+        #[cfg(windows)]
+        return credman::get(service, account);
+        #[cfg(not(windows))]
+        {
+            let secret = if cfg!(target_os = "macos") {
+                run(Command::new("security")
+                        .args(["find-generic-password", "-w", "-s", service, "-a", account]),
+                    None)?
+            } else {
+                run(Command::new("secret-tool")
+                        .args(["lookup", "service", service, "account", account]),
+                    None)?
+            };
+            Ok(secret.map(|s| s.strip_suffix('\n').map(str::to_string).unwrap_or(s)))
+        }
+    }
+
+    fn set(&self, service: &str, account: &str, secret: &str) -> io::Result<()> {
+        #[cfg(windows)]
+        return credman::set(service, account, secret);
+        #[cfg(not(windows))]
+        {
+            let stored = if cfg!(target_os = "macos") {
+                // `security` takes the secret only as an argument, so give
+                // it a command on standard input instead, which has to fit
+                // on one line.  Its exit status does not tell whether that
+                // command failed, so read the secret back.
+                if secret.contains(['\n', '\r']) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "Keychain secrets cannot hold line breaks"));
+                }
+                let command = ["add-generic-password", "-U", "-s", service, "-a", account,
+                               "-w", secret]
+                    .iter()
+                    .map(|arg| security_quote(arg))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                run(Command::new("security").arg("-i"), Some(&format!("{}\n", command)))?;
+                self.get(service, account)?.filter(|stored| stored == secret)
+            } else {
+                let label = format!("{} {}", service, account);
+                run(Command::new("secret-tool")
+                        .args(["store", "--label", &label, "service", service,
+                               "account", account]),
+                    Some(secret))?
+            };
+            match stored {
+                Some(_) => Ok(()),
+                None    => Err(io::Error::new(io::ErrorKind::Other,
+                    format!("Failed to store `{}' in keyring service `{}'", account, service))),
+            }
+        }
+    }
+}
+
+/// Generic credentials of the Windows Credential Manager, each named
+/// `SERVICE/ACCOUNT` and holding the secret as UTF-8.
+#[cfg(windows)]
+mod credman {
+    use std::ffi::c_void;
+    use std::io;
+    use std::ptr;
+
+    const CRED_TYPE_GENERIC: u32 = 1;
+    const CRED_PERSIST_LOCAL_MACHINE: u32 = 2;
+    const ERROR_NOT_FOUND: i32 = 1168;
+
+    #[repr(C)]
+    struct FileTime {
+        low: u32,
+        high: u32,
+    }
+
+    /// `CREDENTIALW` of `wincred.h`.
+    #[repr(C)]
+    struct Credential {
+        flags: u32,
+        kind: u32,
+        target_name: *mut u16,
+        comment: *mut u16,
+        last_written: FileTime,
+        blob_size: u32,
+        blob: *mut u8,
+        persist: u32,
+        attribute_count: u32,
+        attributes: *mut c_void,
+        target_alias: *mut u16,
+        user_name: *mut u16,
+    }
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn CredReadW(target: *const u16, kind: u32, flags: u32,
+                     credential: *mut *mut Credential) -> i32;
+        fn CredWriteW(credential: *const Credential, flags: u32) -> i32;
+        fn CredFree(buffer: *mut c_void);
+    }
+
+    /// `s` as a NUL-terminated UTF-16 string.
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    pub(super) fn get(service: &str, account: &str) -> io::Result<Option<String>> {
+        let target = wide(&format!("{}/{}", service, account));
+        let mut credential = ptr::null_mut();
+        // Safety: `target` is NUL-terminated, and `credential` is freed
+        // with `CredFree` once its blob is copied.
+        unsafe {
+            if CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) == 0 {
+                let e = io::Error::last_os_error();
+                return match e.raw_os_error() {
+                    Some(ERROR_NOT_FOUND) => Ok(None),
+                    _                     => Err(e),
+                };
+            }
+            let blob = std::slice::from_raw_parts((*credential).blob,
+                                                  (*credential).blob_size as usize);
+            let secret = String::from_utf8(blob.to_vec());
+            #[cfg(feature = "secure")]
+            crate::secret::wipe(std::slice::from_raw_parts_mut((*credential).blob,
+                                                               (*credential).blob_size as usize));
+            CredFree(credential as *mut c_void);
+            secret.map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+    }
+
+    pub(super) fn set(service: &str, account: &str, secret: &str) -> io::Result<()> {
+        let mut target = wide(&format!("{}/{}", service, account));
+        let mut user_name = wide(account);
+        let credential = Credential {
+            flags: 0,
+            kind: CRED_TYPE_GENERIC,
+            target_name: target.as_mut_ptr(),
+            comment: ptr::null_mut(),
+            last_written: FileTime { low: 0, high: 0 },
+            blob_size: secret.len() as u32,
+            blob: secret.as_ptr() as *mut u8,
+            persist: CRED_PERSIST_LOCAL_MACHINE,
+            attribute_count: 0,
+            attributes: ptr::null_mut(),
+            target_alias: ptr::null_mut(),
+            user_name: user_name.as_mut_ptr(),
+        };
+        // Safety: every pointer is valid for the call, and `CredWriteW`
+        // does not write through the blob.
+        if unsafe { CredWriteW(&credential, 0) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Machine {
+    /// The service and account of a password written
+    /// `@keyring:SERVICE/ACCOUNT`, if it is one.  The account may contain
+    /// `/`.
+    ///
+    /// Only available with the `keyring` feature.
+    pub fn keyring_entry(&self) -> Option<(&str, &str)> {
+        self.password.as_ref()?.expose().strip_prefix(PREFIX)?.split_once('/')
+    }
+
+    /// The password, looked up in `store` if it refers to a keyring
+    /// entry.  Fails with `Error::SecretNotFound` if there is no such
+    /// entry.
+    ///
+    /// Only available with the `keyring` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use netrc::{Netrc, SystemKeyring};
+    ///
+    /// let input: &[u8] = b"machine example.com login foo password @keyring:netrc/foo";
+    /// let netrc = Netrc::parse(input).unwrap();
+    /// let password = netrc.hosts[0].1.resolve_password(&SystemKeyring).unwrap();
+    /// ```
+    pub fn resolve_password<S: SecretStore>(&self, store: &S) -> Result<Option<Secret>> {
+        let (service, account) = match self.keyring_entry() {
+            Some(entry) => entry,
+            None        => return Ok(self.password.clone()),
+        };
+        match store.get(service, account)? {
+            Some(secret) => Ok(Some(secret.into())),
+            None         => Err(Error::SecretNotFound { service: service.to_string(),
+                                                        account: account.to_string() }),
+        }
+    }
+}
+
+impl Netrc {
+    /// Replace every password referring to a keyring entry with the secret
+    /// in `store`, `default` included.  On error `self` is unchanged.
+    ///
+    /// Only available with the `keyring` feature.
+    pub fn resolve_keyring<S: SecretStore>(&mut self, store: &S) -> Result<()> {
+        let mut hosts = self.hosts.clone();
+        let mut default = self.default.clone();
+        for mach in hosts.iter_mut().map(|(_, mach)| mach).chain(&mut default) {
+            mach.password = mach.resolve_password(store)?;
+        }
+        self.hosts = hosts;
+        self.default = default;
+        Ok(())
+    }
+
+    /// Move every plain password into `store` under `service`, replacing it
+    /// with a reference to its keyring entry, for the netrc to be saved
+    /// without secrets.  Each entry is named `LOGIN@HOST`, or just `HOST`
+    /// without a login, with `default` for the host of the `default` entry.
+    /// Passwords already in a keyring are left alone.  Stops at the first
+    /// failure, keeping what was moved so far.
+    ///
+    /// Only available with the `keyring` feature.
+    pub fn migrate_to_keyring<S: SecretStore>(&mut self, store: &S, service: &str) -> Result<()> {
+        let machines = self.hosts.iter_mut()
+            .map(|(host, mach)| (host.as_str(), mach))
+            .chain(self.default.as_mut().map(|mach| ("default", mach)));
+        for (host, mach) in machines {
+            if mach.keyring_entry().is_some() {
+                continue;
+            }
+            let password = match mach.password {
+                Some(ref password) => password,
+                None               => continue,
+            };
+            let account = if mach.login.is_empty() {
+                host.to_string()
+            } else {
+                format!("{}@{}", mach.login, host)
+            };
+            store.set(service, &account, password.expose())?;
+            mach.password = Some(format!("{}{}/{}", PREFIX, service, account).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SecretStore;
+    use crate::{Error, Netrc};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::io;
+
+    #[derive(Default)]
+    struct MemoryStore(RefCell<HashMap<(String, String), String>>);
+
+    impl SecretStore for MemoryStore {
+        fn get(&self, service: &str, account: &str) -> io::Result<Option<String>> {
+            let key = (service.to_string(), account.to_string());
+            Ok(self.0.borrow().get(&key).cloned())
+        }
+
+        fn set(&self, service: &str, account: &str, secret: &str) -> io::Result<()> {
+            let key = (service.to_string(), account.to_string());
+            self.0.borrow_mut().insert(key, secret.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn migrate_and_resolve() {
+        let input = "machine a.com login a password pa
+                     machine b.com password @keyring:other/b
+                     machine c.com login c
+                     default password pd";
+        let mut netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let store = MemoryStore::default();
+        netrc.migrate_to_keyring(&store, "netrc").unwrap();
+        assert_eq!(netrc.to_string(),
+                   "machine a.com login a password @keyring:netrc/a@a.com\n\
+                    machine b.com password @keyring:other/b\n\
+                    machine c.com login c\n\
+                    default password @keyring:netrc/default\n");
+        assert_eq!(netrc.hosts[0].1.keyring_entry(), Some(("netrc", "a@a.com")));
+
+        let mut resolved = netrc.clone();
+        match resolved.resolve_keyring(&store).unwrap_err() {
+            Error::SecretNotFound { service, account } => {
+                assert_eq!(service, "other");
+                assert_eq!(account, "b");
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        assert_eq!(resolved.to_string(), netrc.to_string());
+        store.set("other", "b", "pb").unwrap();
+        resolved.resolve_keyring(&store).unwrap();
+        let passwords: Vec<_> = resolved.hosts.iter().map(|(_, mach)| &mach.password)
+            .chain(resolved.default.as_ref().map(|mach| &mach.password))
+            .map(|p| p.as_ref().map(|p| p.expose().to_string()))
+            .collect();
+        assert_eq!(passwords, [Some("pa".to_string()), Some("pb".to_string()), None,
+                               Some("pd".to_string())]);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn security_quote() {
+        assert_eq!(super::security_quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+}
@@ -1,351 +1,3153 @@
-use std::io::BufRead;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::convert::TryFrom;
+use core::fmt::{self, Write as _};
+use core::iter::FromIterator;
+#[cfg(feature = "std")]
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::io::{BufRead, Read};
+
+#[cfg(feature = "tokio")]
+mod async_parse;
+mod borrowed;
+mod builder;
+pub mod document;
+#[cfg(feature = "std")]
+mod env;
+pub mod events;
+#[cfg(feature = "fs")]
+mod file;
+#[cfg(feature = "gpg")]
+mod gpg;
+#[cfg(feature = "keyring")]
+mod keyring;
+#[cfg(feature = "ipnet")]
+mod cidr;
+#[cfg(feature = "std")]
+mod index;
+#[cfg(feature = "serde")]
+mod json;
+pub mod lint;
+mod parser;
+mod secret;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "std")]
+mod tokenizer;
+
+use crate::parser::{default_port, Builder, Parser};
+#[cfg(feature = "std")]
+use crate::secret::{line_capacity, LineBuffer, SecretReader};
+
+pub use crate::borrowed::{MachineRef, MacroRef, NetrcRef};
+pub use crate::builder::{MachineBuilder, NetrcBuilder};
+#[cfg(feature = "std")]
+pub use crate::index::NetrcIndex;
+#[cfg(feature = "std")]
+pub use crate::env::UndefinedVarPolicy;
+#[cfg(feature = "keyring")]
+pub use crate::keyring::{SecretStore, SystemKeyring};
+#[cfg(feature = "fs")]
+pub use crate::file::{append_machine, default_path, LocationOptions};
+pub use crate::secret::Secret;
+#[cfg(feature = "std")]
+pub use crate::tokenizer::{Token, TokenKind, Tokenizer};
 
-pub type Macro = (String, String);
 pub type Host = (String, Machine);
 
-#[derive(Debug, Default)]
-pub struct Machine {
-    pub login: String,
-    pub password: Option<String>,
-    pub account: Option<String>,
-    pub port: Option<u16>,
-}
+/// A macro defined with `macdef`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Macro {
+    pub name: String,
+    /// The raw text following the macro name, exactly as read: the rest of
+    /// the `macdef` line including its line break (usually just `"\n"`),
+    /// then every command line with its line break, then the blank line
+    /// `"\n"` that ends the definition, even if it held whitespace.  A
+    /// macro ended by end of input is stored the same way, with the line
+    /// breaks it lacks added, so the body always ends with `"\n\n"`.  See
+    /// `body_trimmed` for just the commands.
+    pub body: String,
+    /// First and last line of the body, both inclusive.  The body starts
+    /// on the `macdef` line and ends on its terminating blank line, or on
+    /// the last line of input.  `None` if the macro was not parsed.
+    pub lines: Option<(usize, usize)>,
+}
+
+impl Macro {
+    /// The command lines of the body, without the blank rest of the
+    /// `macdef` line before them or the line break and blank line after
+    /// them.
+    pub fn body_trimmed(&self) -> &str {
+        let body = match self.body.find('\n') {
+            Some(i) if self.body[..i].trim().is_empty() => &self.body[i + 1..],
+            _ if self.body.trim().is_empty()            => "",
+            _                                           => &self.body,
+        };
+        body.trim_end_matches(['\n', '\r'])
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Machine {
+    pub login: String,
+    /// Left out when serialized if `None`, as after `Netrc::redacted`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub password: Option<Secret>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub account: Option<Secret>,
+    pub port: Option<u16>,
+    /// A service name such as `imaps` given as the port.  Only set with
+    /// `ParseOptions.port_names`, and then `port` is `None`.
+    pub port_name: Option<String>,
+    pub protocol: Option<String>,
+    /// Fields with other keywords, such as `force yes` in `.authinfo`
+    /// files, in input order.  Only set with `ParseOptions.extra_fields`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub extras: Vec<(String, String)>,
+    /// The line of the host name of a `machine` entry, or of the `default`
+    /// keyword.  `None` if the entry was not parsed.
+    pub line: Option<usize>,
+}
+
+impl Machine {
+    /// A copy without the secrets: `password` and `account` are `None`.
+    /// Useful for caching the non-secret parts of an entry.
+    pub fn metadata_only(&self) -> Machine {
+        Machine { password: None, account: None, ..self.clone() }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Netrc {
+    pub hosts: Vec<Host>,
+    pub default: Option<Machine>,
+    pub macros: Vec<Macro>,
+    /// The number of lines of input.  `None` if the `Netrc` was not parsed.
+    pub lines: Option<usize>,
+}
+
+/// A summary of a `Netrc`, from `Netrc::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NetrcStats {
+    pub hosts: usize,
+    pub has_default: bool,
+    pub macros: usize,
+    /// See `Netrc::lines`.
+    pub total_lines: Option<usize>,
+}
+
+/// Why parsing or a lookup failed.  New variants may be added, so a
+/// `match` on it needs a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading or writing failed, whatever the contents.  Unlike the other
+    /// variants, retrying may succeed.
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// Malformed input, with the line number where it was detected.
+    Parse { msg: String, line: usize },
+    /// A word that is not a keyword where an entry should start, with the
+    /// line number where it was found.
+    UnknownEntry { keyword: String, line: usize },
+    /// An environment variable referenced in a value is not set.  See
+    /// `Netrc::expand_env`.
+    UndefinedVariable(String),
+    /// Neither a `machine` entry nor `default` applies to the host.  See
+    /// `Netrc::get_required`.
+    HostNotFound(String),
+    /// None of the places searched for the user's netrc file has one.  See
+    /// `Netrc::from_default_location`.
+    #[cfg(feature = "std")]
+    NoNetrcFile(Vec<std::path::PathBuf>),
+    /// A netrc file with passwords that other users could read or that
+    /// someone else owns.  See `Netrc::from_file_secure`.
+    #[cfg(feature = "std")]
+    InsecurePermissions { path: std::path::PathBuf, msg: String },
+    /// A password refers to a keyring entry that does not exist.  See
+    /// `Machine::resolve_password`.
+    SecretNotFound { service: String, account: String },
+    /// Both netrcs merged with `MergeStrategy::ErrorOnConflict` have the
+    /// entry or macro described.  See `Netrc::merge`.
+    MergeConflict(String),
+}
+
+pub type Result<A> = core::result::Result<A, Error>;
+
+/// Something odd but valid in the input, reported by
+/// `Netrc::parse_with_warnings`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub line: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WarningKind {
+    /// A macro with nothing but whitespace in its body.  Reported at the
+    /// line of its `macdef`.
+    EmptyMacro,
+    /// A `login` made of digits only, which may be a misplaced port or
+    /// account.
+    NumericLogin,
+    /// A `password` starting or ending with whitespace, which is easily
+    /// lost when quoting it.
+    PasswordWhitespace,
+    /// A word that is not a keyword where an entry should start, skipped
+    /// with `UnknownEntryPolicy::Warn`.
+    UnknownEntry,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            WarningKind::EmptyMacro         => "Macro with an empty body",
+            WarningKind::NumericLogin       => "Login is a number",
+            WarningKind::PasswordWhitespace => "Password starts or ends with whitespace",
+            WarningKind::UnknownEntry       => "Unknown entry skipped",
+        })
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}", self.kind, self.line)
+    }
+}
+
+/// Parse a `Netrc` object from byte stream.  Same as `Netrc::parse`, but
+/// the stream needs no buffering of its own.
+///
+/// # Examples
+///
+/// ```
+/// let input: &[u8] = b"machine example.com login foo password bar";
+/// let netrc = netrc::parse(input).unwrap();
+/// assert_eq!(netrc.hosts[0].1.login, "foo");
+/// ```
+#[cfg(feature = "std")]
+pub fn parse<A: Read>(buf: A) -> Result<Netrc> {
+    Netrc::parse(SecretReader::new(buf))
+}
+
+/// Options controlling how netrc input is parsed.
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// Read a value enclosed in double quotes up to the closing quote, so
+    /// it may contain spaces and tabs.  Inside the quotes, `\"` and `\\`
+    /// stand for a quote and a backslash, and `\n` for a line break.  On by
+    /// default, as curl does, since it is how `Netrc`'s `Display` writes
+    /// values that are empty, have whitespace or start with a quote.
+    pub quoted_values: bool,
+    /// Fill in a missing `port` from the machine's `protocol`, using the
+    /// well-known ports `http` 80, `https` 443 and `ftp` 21.  Other
+    /// protocols leave the port unset.
+    pub infer_port_from_protocol: bool,
+    /// Separate words on ASCII space, tab and line breaks only, as the
+    /// netrc format does, so that a value may contain other whitespace
+    /// such as a no-break space.  By default any Unicode whitespace
+    /// separates words.
+    pub strict_whitespace: bool,
+    /// Also accept a keyword and its value joined by `=` into one word,
+    /// as in `login=foo`.  The value is everything after the first `=`.
+    pub assignments: bool,
+    /// Accept a service name such as `https` as a `port`, storing it in
+    /// `Machine::port_name`.  Names are made of ASCII letters, digits and
+    /// hyphens; an out-of-range number is still an error.
+    pub port_names: bool,
+    /// With `port_names`, store the number of a known service such as
+    /// `imaps` in `port` instead, leaving other names in `port_name`.
+    /// Services are looked up in a built-in table of well-known ports,
+    /// then on Unix with the `fs` feature in `/etc/services`.
+    pub resolve_port_names: bool,
+    /// Strip a leading `scheme://` and trailing slashes from `machine`
+    /// names, so that `https://example.com/` becomes `example.com`.  See
+    /// `Netrc::normalize_hosts`.
+    pub normalize_hosts: bool,
+    /// Split a trailing `:PORT` off `machine` names into the `port`, so
+    /// that `example.com:8080` is read as `example.com` with port 8080.
+    /// An IPv6 address takes brackets, as in `[::1]:8080`, and loses them;
+    /// one without brackets or port, such as `::1`, is left as is, as is a
+    /// name whose port is not a number.
+    pub host_ports: bool,
+    /// Ignore the rest of a line from a word starting with `#` where an
+    /// entry or keyword may start, as GNU ftp does.  Values, such as a
+    /// password starting with `#`, and macro bodies are read as usual.
+    pub comments: bool,
+    /// Accept keywords in any ASCII case, such as `Machine`, `LOGIN` or
+    /// `MacDef`, as some legacy tools write them.
+    pub case_insensitive_keywords: bool,
+    /// Read a word that is not a keyword inside a `machine` or `default`
+    /// entry as the keyword of a field, kept with its value in
+    /// `Machine::extras`, instead of as an unknown entry.
+    pub extra_fields: bool,
+    /// What to do with a word that is not a keyword where an entry should
+    /// start.
+    pub unknown_entries: UnknownEntryPolicy,
+    /// Replace a line `include PATH` by the contents of that file.  Only
+    /// honored by `Netrc::from_file_with_options`.
+    pub includes: bool,
+    /// How deeply `include` directives may nest.  16 by default.
+    pub max_include_depth: usize,
+    /// Accept `machine` names in CIDR notation such as `10.0.0.0/8`,
+    /// rejecting malformed ranges.  See `Netrc::get_ip`.
+    #[cfg(feature = "ipnet")]
+    pub allow_cidr: bool,
+    /// How input is decoded into text.
+    pub encoding: Encoding,
+    /// Maximum length of a line in bytes, line terminator included.
+    /// Longer lines are rejected without being read in full.
+    /// `usize::MAX` lifts the limit.
+    pub max_line_length: usize,
+    /// Maximum number of `machine` entries.
+    pub max_hosts: usize,
+    /// Maximum number of `macdef` entries.
+    pub max_macros: usize,
+    /// Maximum length of a macro body in bytes, which also bounds a
+    /// `macdef` missing its terminating blank line.
+    pub max_macro_length: usize,
+    /// Keep only the `machine` entries whose name the filter accepts.  The
+    /// fields of a rejected entry are still checked but not stored.  The
+    /// `default` entry and macros are always kept.
+    pub host_filter: Option<HostFilter>,
+    /// How to store several `machine` entries with the same name.
+    pub on_duplicate_host: DuplicateHost,
+    /// Reject likely mistakes that are valid netrc syntax: a `machine`
+    /// entry with no fields, as when its host name is missing or when a
+    /// `default` entry before it took the fields meant for it, a keyword
+    /// in place of a host name or field value, as in `login password p`,
+    /// and a field given twice in one entry.
+    pub strict: bool,
+}
+
+/// How to decode input, for `ParseOptions.encoding`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8, failing with `Error::Io` on invalid input.
+    #[default]
+    Utf8,
+    /// UTF-8, with invalid sequences replaced by U+FFFD.
+    Utf8Lossy,
+    /// ISO 8859-1, where each byte stands for the character of that code.
+    /// Any input is valid.
+    Latin1,
+}
+
+impl Encoding {
+    /// Append `bytes`, decoded, to `text`.  Not for `Utf8`, which is read
+    /// directly so that invalid input fails.
+    #[cfg(feature = "std")]
+    fn decode(self, bytes: &[u8], text: &mut String) {
+        match self {
+            Encoding::Utf8      => unreachable!("decoding strict UTF-8"),
+            Encoding::Utf8Lossy => text.push_str(&String::from_utf8_lossy(bytes)),
+            Encoding::Latin1    => text.extend(bytes.iter().map(|&b| char::from(b))),
+        }
+    }
+}
+
+/// What to do with a word that is not a keyword where an entry should
+/// start, for `ParseOptions.unknown_entries`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownEntryPolicy {
+    /// Fail with `Error::UnknownEntry`.
+    #[default]
+    Error,
+    /// Ignore the word.
+    Skip,
+    /// Ignore the word, reporting `WarningKind::UnknownEntry`.
+    Warn,
+}
+
+/// What to do with a `machine` entry whose name is already taken, for
+/// `ParseOptions.on_duplicate_host`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateHost {
+    /// Drop the later entries.
+    KeepFirst,
+    /// Drop the earlier entries, so the last one stays in its place.
+    KeepLast,
+    /// Store every entry in file order.  Lookups such as `get` find the
+    /// first.
+    #[default]
+    KeepAll,
+    /// Reject the file at the first duplicate.
+    Error,
+    /// Fold the later entries into the first one, in its place.  A field
+    /// given in several entries takes its last value.
+    Merge,
+}
+
+/// A predicate on `machine` names for `ParseOptions.host_filter`.
+#[derive(Clone)]
+pub struct HostFilter(Arc<dyn Fn(&str) -> bool + Send + Sync>);
+
+impl HostFilter {
+    pub fn new<F: Fn(&str) -> bool + Send + Sync + 'static>(filter: F) -> HostFilter {
+        HostFilter(Arc::new(filter))
+    }
+
+    /// Whether the entry for `host` is kept.
+    pub fn accepts(&self, host: &str) -> bool {
+        (self.0)(host)
+    }
+}
+
+impl fmt::Debug for HostFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("HostFilter")
+    }
+}
+
+impl Default for ParseOptions {
+    /// No extensions enabled but `quoted_values`, and limits far above
+    /// what a hand-written netrc file needs.
+    fn default() -> ParseOptions {
+        ParseOptions {
+            quoted_values: true,
+            infer_port_from_protocol: false,
+            strict_whitespace: false,
+            assignments: false,
+            port_names: false,
+            resolve_port_names: false,
+            normalize_hosts: false,
+            host_ports: false,
+            comments: false,
+            case_insensitive_keywords: false,
+            extra_fields: false,
+            unknown_entries: UnknownEntryPolicy::Error,
+            includes: false,
+            max_include_depth: 16,
+            #[cfg(feature = "ipnet")]
+            allow_cidr: false,
+            encoding: Encoding::Utf8,
+            max_line_length: 64 * 1024,
+            max_hosts: 100_000,
+            max_macros: 10_000,
+            max_macro_length: 1024 * 1024,
+            host_filter: None,
+            on_duplicate_host: DuplicateHost::KeepAll,
+            strict: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Options for the `.authinfo` files of Emacs and Gnus, a variant of
+    /// netrc with service names as ports, as in `port imaps`, quoted
+    /// values, comments and other keywords such as `force yes`, which end
+    /// up in `Machine::extras`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::{Netrc, ParseOptions};
+    ///
+    /// let input = "machine imap.example.com login foo password \"a b\" port imaps force yes";
+    /// let netrc = Netrc::parse_str(input, &ParseOptions::authinfo()).unwrap();
+    /// let mach = &netrc.hosts[0].1;
+    /// assert_eq!(mach.port_name.as_deref(), Some("imaps"));
+    /// assert_eq!(mach.extras, [("force".to_string(), "yes".to_string())]);
+    /// assert_eq!(netrc.to_string(), format!("{}\n", input));
+    /// ```
+    pub fn authinfo() -> ParseOptions {
+        ParseOptions {
+            quoted_values: true,
+            port_names: true,
+            comments: true,
+            extra_fields: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Options controlling how lookups such as `Netrc::machine_for` match
+/// host names.
+#[derive(Clone, Debug)]
+pub struct LookupOptions {
+    /// Let an entry whose name is a pattern match hosts, compared
+    /// case-insensitively, when the host has no entry of its own.  In a
+    /// pattern, `?` stands for any one character and `*` for one or more,
+    /// so that `*.example.com` matches any subdomain of `example.com`.  The
+    /// pattern with the most other characters wins, the first on a tie.
+    pub wildcard: bool,
+    /// Fall back to the `default` entry, as `ftp` does.  Without it, a host
+    /// with no entry of its own has no credentials, as for tools that never
+    /// consult `default`.
+    pub use_default: bool,
+}
+
+impl Default for LookupOptions {
+    /// Exact host names, then `default`.
+    fn default() -> LookupOptions {
+        LookupOptions { wildcard: false, use_default: true }
+    }
+}
+
+/// Whether `name` is a pattern for `LookupOptions.wildcard`.
+fn is_wildcard(name: &str) -> bool {
+    name.contains(['*', '?'])
+}
+
+/// Whether `host` matches `pattern`, ignoring ASCII case, where `?` stands
+/// for any one character and `*` for one or more.
+fn wildcard_match(pattern: &str, host: &str) -> bool {
+    let (p, h) = (pattern.as_bytes(), host.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    // Just after the last `*` seen, and where its match ends in `host`.
+    let mut star = None;
+    while j < h.len() {
+        match p.get(i) {
+            Some(b'*') => {
+                star = Some((i + 1, j + 1));
+                i += 1;
+                j += 1;
+            }
+            Some(&c) if c == b'?' || c.eq_ignore_ascii_case(&h[j]) => {
+                i += 1;
+                j += 1;
+            }
+            // Let the last `*` match one more character and retry.
+            _ => match star {
+                Some((after, ref mut end)) => {
+                    *end += 1;
+                    i = after;
+                    j = *end;
+                }
+                None => return false,
+            },
+        }
+    }
+    i == p.len()
+}
+
+/// Whether `a` and `b` name the same host, ignoring ASCII case as DNS
+/// does.  This is how every lookup compares a `machine` name with a host.
+pub fn same_host(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+/// Whether `s` is a URL scheme such as `https` or `git+ssh`.
+fn is_scheme(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+}
+
+/// `host` without a leading `scheme://` and trailing slashes.
+fn normalize_host(host: &str) -> &str {
+    let host = match host.split_once("://") {
+        Some((scheme, rest)) if is_scheme(scheme) => rest,
+        _                                         => host,
+    };
+    host.trim_end_matches('/')
+}
+
+/// The host and port of `url`, such as `example.com` and 8080 for
+/// `https://user@example.com:8080/path`.  Without a port in `url`, the
+/// well-known port of its scheme, if any.  `None` if `url` has no scheme.
+fn url_host_port(url: &str) -> Option<(&str, Option<u16>)> {
+    let (scheme, rest) = url.split_once("://").filter(|(scheme, _)| is_scheme(scheme))?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let hostport = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    let (host, port) = split_host_port(hostport)?;
+    let port = match port.filter(|p| !p.is_empty()) {
+        Some(port) => Some(port.parse().ok()?),
+        None       => default_port(scheme),
+    };
+    Some((host, port))
+}
+
+/// `hostport` split at the `:` before the port, if any, with the brackets
+/// around an IPv6 address as in `[::1]:8080` removed.  `None` if a bracket
+/// is not closed.
+fn split_host_port(hostport: &str) -> Option<(&str, Option<&str>)> {
+    match hostport.strip_prefix('[') {
+        Some(rest) => {
+            let (host, after) = rest.split_once(']')?;
+            Some((host, after.strip_prefix(':')))
+        }
+        None       => match hostport.rsplit_once(':') {
+            Some((host, port)) => Some((host, Some(port))),
+            None               => Some((hostport, None)),
+        },
+    }
+}
+
+/// Skip past the next `\n`, or to the end of `buf`.
+#[cfg(feature = "std")]
+fn skip_line<A: BufRead>(buf: &mut A) -> std::io::Result<()> {
+    loop {
+        let available = buf.fill_buf()?;
+        if available.is_empty() {
+            return Ok(());
+        }
+        match available.iter().position(|&b| b == b'\n') {
+            Some(i) => { buf.consume(i + 1); return Ok(()); }
+            None    => { let n = available.len(); buf.consume(n); }
+        }
+    }
+}
+
+/// Read a line like `BufRead::read_line`, but stop after one byte more than
+/// `max` so that an overlong line is not buffered in full, and decode it
+/// as `encoding` says.
+#[cfg(feature = "std")]
+fn read_line<A: BufRead>(buf: &mut A,
+                         line: &mut String,
+                         max: usize,
+                         encoding: Encoding) -> std::io::Result<usize> {
+    let mut buf = buf.take((max as u64).saturating_add(1));
+    if encoding == Encoding::Utf8 {
+        return buf.read_line(line);
+    }
+    let mut bytes = Vec::with_capacity(line_capacity(max, 1));
+    let len = buf.read_until(b'\n', &mut bytes)?;
+    encoding.decode(&bytes, line);
+    #[cfg(feature = "secure")]
+    crate::secret::wipe(&mut bytes);
+    Ok(len)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "std")]
+            Error::Io(ref e)                => write!(f, "{}", e),
+            Error::Parse { ref msg, line }  => write!(f, "{} at line {}", msg, line),
+            Error::UnknownEntry { ref keyword, line } =>
+                write!(f, "Unknown entry `{}' at line {}", keyword, line),
+            Error::UndefinedVariable(ref name) =>
+                write!(f, "Undefined environment variable `{}'", name),
+            Error::HostNotFound(ref host) =>
+                write!(f, "No credentials for host `{}'", host),
+            #[cfg(feature = "std")]
+            Error::NoNetrcFile(ref paths) => {
+                write!(f, "No netrc file found")?;
+                for (i, path) in paths.iter().enumerate() {
+                    let sep = if i == 0 { " at " } else { " or " };
+                    write!(f, "{}`{}'", sep, path.display())?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "std")]
+            Error::InsecurePermissions { ref path, ref msg } =>
+                write!(f, "Refusing to use `{}': {}", path.display(), msg),
+            Error::SecretNotFound { ref service, ref account } =>
+                write!(f, "No secret for `{}' in keyring service `{}'", account, service),
+            Error::MergeConflict(ref what) =>
+                write!(f, "Both netrcs have {}", what),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e)    => Some(e),
+            Error::Parse { .. }
+            | Error::UnknownEntry { .. }
+            | Error::UndefinedVariable(_)
+            | Error::HostNotFound(_)
+            | Error::NoNetrcFile(_)
+            | Error::InsecurePermissions { .. }
+            | Error::SecretNotFound { .. }
+            | Error::MergeConflict(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Where the `default` entry is emitted when serializing a `Netrc`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DefaultPosition {
+    First,
+    /// BSD `ftp` requires `default` to follow all `machine` entries.
+    #[default]
+    Last,
+}
+
+/// What `Netrc::merge` does with an entry or macro both netrcs have.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Keep the one already there.
+    #[default]
+    KeepExisting,
+    /// Replace it with the other one, in place.
+    PreferOther,
+    /// Fail with `Error::MergeConflict`.
+    ErrorOnConflict,
+}
+
+/// Options controlling how a `Netrc` is serialized.  Whatever the options,
+/// fields are written in the order `login`, `password`, `account`, `port`,
+/// `protocol`, then any others, separated by single spaces.
+#[derive(Clone, Debug, Default)]
+pub struct NetrcFormat {
+    pub default_position: DefaultPosition,
+    /// Write each field on a line of its own, indented by this many spaces,
+    /// instead of all on the line of the host name.
+    pub field_indent: Option<usize>,
+    /// Separate entries and macros by a blank line.  A macro is followed
+    /// by its terminating blank line either way.
+    pub blank_lines: bool,
+    /// Strip the whitespace around each command of a macro, and any after
+    /// its name.
+    pub trim_macros: bool,
+}
+
+impl NetrcFormat {
+    /// A layout for netrc files kept in version control, diffing one field
+    /// per line: `default` last, fields indented by 4 spaces, blank lines
+    /// between entries, and macros trimmed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::{Machine, Netrc, NetrcFormat};
+    ///
+    /// let netrc = Netrc::builder()
+    ///     .machine("a.com", Machine::builder().login("a").password("p").build())
+    ///     .default_machine(Machine::builder().login("anonymous").build())
+    ///     .build();
+    /// assert_eq!(netrc.to_string_with(&NetrcFormat::canonical()),
+    ///            "machine a.com\n    login a\n    password p\n\ndefault\n    login anonymous\n");
+    /// ```
+    pub fn canonical() -> NetrcFormat {
+        NetrcFormat {
+            default_position: DefaultPosition::Last,
+            field_indent: Some(4),
+            blank_lines: true,
+            trim_macros: true,
+        }
+    }
+}
+
+impl Netrc {
+    /// Parse a `Netrc` object from byte stream.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    /// use std::io::Cursor;
+    ///
+    /// let input: Cursor<&[u8]> =
+    ///   Cursor::new(b"machine example.com login foo password bar");
+    /// let netrc = Netrc::parse(input).unwrap();
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn parse<A: BufRead>(buf: A) -> Result<Netrc> {
+        Netrc::parse_with_options(buf, &Default::default())
+    }
+
+    /// Parse a `Netrc` object from bytes in memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    /// use std::convert::TryFrom;
+    ///
+    /// let input = b"machine example.com login foo password bar";
+    /// let netrc = Netrc::from_bytes(input).unwrap();
+    /// let same = Netrc::try_from(&input[..]).unwrap();
+    /// assert_eq!(netrc.to_string(), same.to_string());
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Netrc> {
+        Netrc::parse(bytes)
+    }
+
+    /// Parse a netrc held in memory as text with the given options.  Unlike
+    /// the parsers reading a byte stream, this is available without the
+    /// `std` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let netrc = Netrc::parse_str("machine example.com login foo", &Default::default())
+    ///     .unwrap();
+    /// assert_eq!(netrc.hosts[0].1.login, "foo");
+    /// ```
+    pub fn parse_str(input: &str, options: &ParseOptions) -> Result<Netrc> {
+        let mut parser = Parser::new(options, Builder::new());
+        for line in input.split_inclusive('\n') {
+            parser.feed_line(line)?;
+        }
+        parser.finish_netrc()
+    }
+
+    /// Parse like `parse`, but replace invalid UTF-8 in the input, such as
+    /// a Latin-1 character in a macro body, with U+FFFD instead of failing.
+    /// See `ParseOptions.encoding` to decode Latin-1 instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let input: &[u8] = b"machine example.com login ren\xe9";
+    /// assert!(Netrc::parse(input).is_err());
+    /// let netrc = Netrc::parse_lossy(input).unwrap();
+    /// assert_eq!(netrc.hosts[0].1.login, "ren\u{fffd}");
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn parse_lossy<A: BufRead>(buf: A) -> Result<Netrc> {
+        let options = ParseOptions { encoding: Encoding::Utf8Lossy, ..Default::default() };
+        Netrc::parse_with_options(buf, &options)
+    }
+
+    /// Parse a `Netrc` object from byte stream with the given options.
+    #[cfg(feature = "std")]
+    pub fn parse_with_options<A: BufRead>(buf: A,
+                                          options: &ParseOptions) -> Result<Netrc> {
+        Netrc::parse_with_warnings(buf, options).map(|(netrc, _)| netrc)
+    }
+
+    /// Parse a `Netrc` object from byte stream with the given options,
+    /// along with the oddities found in input that parsed successfully.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::{Netrc, WarningKind};
+    /// use std::io::Cursor;
+    ///
+    /// let input: Cursor<&[u8]> = Cursor::new(b"machine example.com login 1234");
+    /// let (netrc, warnings) =
+    ///   Netrc::parse_with_warnings(input, &Default::default()).unwrap();
+    /// assert_eq!(warnings[0].kind, WarningKind::NumericLogin);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn parse_with_warnings<A: BufRead>(buf: A,
+                                           options: &ParseOptions)
+                                           -> Result<(Netrc, Vec<Warning>)> {
+        Netrc::parse_lines(buf, Parser::new(options, Builder::new()))
+    }
+
+    /// Parse without stopping at malformed input: each error is collected,
+    /// and parsing resumes at the next `machine` or `default` keyword.
+    /// Returns every entry read, including the fields an interrupted entry
+    /// got before the error, along with the errors in input order.  Only
+    /// reading failures end the parse.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    /// use std::io::Cursor;
+    ///
+    /// let input: Cursor<&[u8]> =
+    ///   Cursor::new(b"machine a.com port x\nmachine b.com login b\n");
+    /// let (netrc, errors) = Netrc::parse_recovering(input, &Default::default()).unwrap();
+    /// assert_eq!(netrc.hosts.len(), 2);
+    /// assert_eq!(errors[0].to_string(), "Unable to parse port number `x' at line 1");
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn parse_recovering<A: BufRead>(mut buf: A,
+                                        options: &ParseOptions)
+                                        -> Result<(Netrc, Vec<Error>)> {
+        let mut parser = Parser::new(options, Builder::new());
+        parser.errors = Some(Vec::new());
+        let max = options.max_line_length;
+        let mut line = LineBuffer::for_lines(max, options.encoding);
+        while read_line(&mut buf, &mut line, max, options.encoding)? > 0 {
+            if line.len() > max && !line.ends_with('\n') {
+                // Drop the rest of the overlong line rather than read it as
+                // a line of its own.
+                skip_line(&mut buf)?;
+            }
+            parser.feed_line(&line)?;
+            line.clear();
+        }
+        let (netrc, _, errors) = parser.finish_netrc_with_diagnostics()?;
+        Ok((netrc, errors))
+    }
+
+    /// Parse like `parse`, where a value enclosed in double quotes is taken
+    /// verbatim up to the closing quote, so it may contain spaces and tabs.
+    /// An unterminated quote extends to the end of its line.  The same as
+    /// `parse` now that `ParseOptions.quoted_values` is on by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    /// use std::io::Cursor;
+    ///
+    /// let input: Cursor<&[u8]> =
+    ///   Cursor::new(b"machine example.com login foo password \"a\tb\"");
+    /// let netrc = Netrc::parse_accepting_tabs_in_values(input).unwrap();
+    /// assert_eq!(netrc.hosts[0].1.password.as_ref().unwrap().expose(), "a\tb");
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn parse_accepting_tabs_in_values<A: BufRead>(buf: A) -> Result<Netrc> {
+        let options = ParseOptions { quoted_values: true, ..Default::default() };
+        Netrc::parse_with_options(buf, &options)
+    }
+
+    /// Parse like `parse`, but keep only the `machine` entries whose name
+    /// `filter` accepts.  See `ParseOptions.host_filter`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    /// use std::io::Cursor;
+    ///
+    /// let input: Cursor<&[u8]> =
+    ///   Cursor::new(b"machine a.example.com login a machine b.test login b");
+    /// let netrc = Netrc::parse_with_host_filter(
+    ///   input, |host| host.ends_with(".example.com")).unwrap();
+    /// assert_eq!(netrc.hosts.len(), 1);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn parse_with_host_filter<A, F>(buf: A, filter: F) -> Result<Netrc>
+        where A: BufRead,
+              F: Fn(&str) -> bool + Send + Sync + 'static
+    {
+        let options = ParseOptions {
+            host_filter: Some(HostFilter::new(filter)),
+            ..Default::default()
+        };
+        Netrc::parse_with_options(buf, &options)
+    }
+
+    /// Parse the netrc stored at `range` within `bytes`, such as a section
+    /// of a larger blob, without copying it out first.  A range outside of
+    /// `bytes` yields an `Error::Io` of kind `InvalidInput`.
+    #[cfg(feature = "std")]
+    pub fn parse_range(bytes: &[u8], range: Range<usize>) -> Result<Netrc> {
+        match bytes.get(range.clone()) {
+            Some(slice) => Netrc::parse(slice),
+            None        => {
+                let msg = format!("Range {:?} out of bounds for {} bytes",
+                                  range, bytes.len());
+                Err(Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                                  msg)))
+            }
+        }
+    }
+
+    /// Parse a stream holding several netrc files, each separated from the
+    /// next by a line consisting of `delimiter`, into one `Netrc` per
+    /// section.  Line numbers in errors count from the start of the stream.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let input: &[u8] = b"machine a.com login a\n---\nmachine b.com login b\n";
+    /// let sections = Netrc::parse_concatenated_stream(input, "---").unwrap();
+    /// assert_eq!(sections.len(), 2);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn parse_concatenated_stream<A: Read>(buf: A,
+                                              delimiter: &str) -> Result<Vec<Netrc>> {
+        let options = Default::default();
+        let mut buf = SecretReader::new(buf);
+        let mut sections = Vec::new();
+        let mut parser = Parser::new(&options, Builder::new());
+        let mut line = LineBuffer::for_lines(options.max_line_length, options.encoding);
+        while read_line(&mut buf, &mut line, options.max_line_length, options.encoding)? > 0 {
+            if line.trim_end_matches(['\n', '\r']) == delimiter {
+                let lnum = parser.lnum + 1;
+                sections.push(parser.finish_netrc()?);
+                parser = Parser::new(&options, Builder::new());
+                parser.lnum = lnum;
+            } else {
+                parser.feed_line(&line)?;
+            }
+            line.clear();
+        }
+        sections.push(parser.finish_netrc()?);
+        Ok(sections)
+    }
+
+    #[cfg(feature = "std")]
+    fn parse_lines<A: BufRead>(mut buf: A,
+                               mut parser: Parser<Builder>)
+                               -> Result<(Netrc, Vec<Warning>)> {
+        let max = parser.options.max_line_length;
+        let mut line = LineBuffer::for_lines(max, parser.options.encoding);
+        while read_line(&mut buf, &mut line, max, parser.options.encoding)? > 0 {
+            parser.feed_line(&line)?;
+            line.clear();
+        }
+        parser.finish_netrc_with_warnings()
+    }
+
+    /// Look up the first `machine` entry named `host`, as compared by
+    /// `same_host`.  The `default` entry is not consulted.
+    pub fn get(&self, host: &str) -> Option<&Machine> {
+        self.hosts.iter().find(|&(name, _)| same_host(name, host)).map(|(_, mach)| mach)
+    }
+
+    /// Look up every `machine` entry named `host`, in file order, for when
+    /// duplicate entries are intentional.  With `include_default` the
+    /// `default` entry, if any, is appended.
+    pub fn lookup_all(&self, host: &str, include_default: bool) -> Vec<&Machine> {
+        let hosts = self.hosts.iter()
+            .filter(|(name, _)| same_host(name, host))
+            .map(|(_, mach)| mach);
+        let default = self.default.as_ref().filter(|_| include_default);
+        hosts.chain(default).collect()
+    }
+
+    /// The line where the first `machine` entry named `host` starts, for
+    /// telling the user where its credentials come from.
+    pub fn line_of(&self, host: &str) -> Option<usize> {
+        self.get(host).and_then(|mach| mach.line)
+    }
+
+    /// Replace the first `machine` entry named `host` with `machine`,
+    /// keeping its position, or append a new entry if there is none.
+    pub fn upsert_machine(&mut self, host: &str, machine: Machine) {
+        match self.hosts.iter_mut().find(|(name, _)| same_host(name, host)) {
+            Some((_, mach)) => *mach = machine,
+            None            => self.hosts.push((host.to_string(), machine)),
+        }
+    }
+
+    /// The `machine` entries as host and machine, in order, without
+    /// `default`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let input = "machine a.com login a machine b.com login b default login anon";
+    /// let netrc = Netrc::parse_str(input, &Default::default()).unwrap();
+    /// let logins: Vec<_> = netrc.machines().map(|(_, mach)| mach.login.as_str()).collect();
+    /// assert_eq!(logins, ["a", "b"]);
+    /// assert_eq!(netrc.machine_names().collect::<Vec<_>>(), ["a.com", "b.com"]);
+    /// ```
+    pub fn machines(&self) -> Machines<'_> {
+        Machines(self.hosts.iter())
+    }
+
+    /// The host names of the `machine` entries, in order.
+    pub fn machine_names(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator {
+        self.hosts.iter().map(|(host, _)| host.as_str())
+    }
+
+    /// The macros, in order.
+    pub fn macros(&self) -> core::slice::Iter<'_, Macro> {
+        self.macros.iter()
+    }
+
+    /// Add the entries and macros of `other`, as when reading a system-wide
+    /// netrc and then the user's.  A `machine` entry conflicts with one for
+    /// the same host, as compared by `same_host`, and the same port; the
+    /// `default` entries conflict; and macros conflict by name.  `strategy`
+    /// decides which of the two is kept, and anything without a conflict
+    /// is appended.  On error `self` is unchanged.  Line numbers are kept
+    /// as they were in either netrc.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::{MergeStrategy, Netrc};
+    ///
+    /// let options = Default::default();
+    /// let mut netrc = Netrc::parse_str("machine a.com login sys default login anon", &options)
+    ///     .unwrap();
+    /// let user = Netrc::parse_str("machine a.com login me machine b.com login me", &options)
+    ///     .unwrap();
+    /// netrc.merge(user, MergeStrategy::PreferOther).unwrap();
+    /// assert_eq!(netrc.to_string(),
+    ///            "machine a.com login me\nmachine b.com login me\ndefault login anon\n");
+    /// ```
+    pub fn merge(&mut self, other: Netrc, strategy: MergeStrategy) -> Result<()> {
+        let conflict = |what: String| match strategy {
+            MergeStrategy::ErrorOnConflict => Err(Error::MergeConflict(what)),
+            _                              => Ok(strategy == MergeStrategy::PreferOther),
+        };
+        let mut hosts = Vec::new();
+        for (host, mach) in other.hosts {
+            let existing = self.hosts.iter().position(|(name, existing)| {
+                same_host(name, &host)
+                    && existing.port == mach.port
+                    && existing.port_name == mach.port_name
+            });
+            match existing {
+                Some(i) => if conflict(format!("an entry for `{}'", host))? {
+                    hosts.push((Some(i), (host, mach)));
+                },
+                None    => hosts.push((None, (host, mach))),
+            }
+        }
+        let default = match (&self.default, other.default) {
+            (Some(_), Some(mach)) => {
+                if conflict("`default'".to_string())? { Some(mach) } else { None }
+            }
+            (None, mach)          => mach,
+            (Some(_), None)       => None,
+        };
+        let mut macros = Vec::new();
+        for mac in other.macros {
+            match self.macros.iter().position(|existing| existing.name == mac.name) {
+                Some(i) => if conflict(format!("macro `{}'", mac.name))? {
+                    macros.push((Some(i), mac));
+                },
+                None    => macros.push((None, mac)),
+            }
+        }
+        for (i, host) in hosts {
+            match i {
+                Some(i) => self.hosts[i] = host,
+                None    => self.hosts.push(host),
+            }
+        }
+        if default.is_some() {
+            self.default = default;
+        }
+        for (i, mac) in macros {
+            match i {
+                Some(i) => self.macros[i] = mac,
+                None    => self.macros.push(mac),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether there are no entries at all, not even `default` or a macro.
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty() && self.default.is_none() && self.macros.is_empty()
+    }
+
+    /// Whether `resolve` finds a non-empty login or password for `host`.
+    pub fn has_credentials_for(&self, host: &str) -> bool {
+        match self.resolve(host) {
+            Some(mach) => !mach.login.is_empty()
+                          || mach.password.is_some_and(|p| !p.expose().is_empty()),
+            None       => false,
+        }
+    }
+
+    /// Count the entries, for logging or for rejecting oversized input.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let input = "machine example.com login foo\ndefault login bar\n";
+    /// let stats = Netrc::parse_str(input, &Default::default()).unwrap().stats();
+    /// assert_eq!(stats.hosts, 1);
+    /// assert_eq!(stats.total_lines, Some(2));
+    /// ```
+    pub fn stats(&self) -> NetrcStats {
+        NetrcStats {
+            hosts: self.hosts.len(),
+            has_default: self.default.is_some(),
+            macros: self.macros.len(),
+            total_lines: self.lines,
+        }
+    }
+
+    /// Copy the entries without their passwords and accounts, for logging
+    /// or serializing the rest.
+    pub fn redacted(&self) -> Netrc {
+        Netrc {
+            hosts: self.hosts.iter()
+                .map(|(name, mach)| (name.clone(), mach.metadata_only()))
+                .collect(),
+            default: self.default.as_ref().map(Machine::metadata_only),
+            macros: self.macros.clone(),
+            lines: self.lines,
+        }
+    }
+
+    /// Remove every `machine` entry named `host`, returning whether there
+    /// was any.
+    pub fn remove_host(&mut self, host: &str) -> bool {
+        let len = self.hosts.len();
+        self.hosts.retain(|(name, _)| !same_host(name, host));
+        self.hosts.len() != len
+    }
+
+    /// Rename every `machine` entry named `old` to `new`, in place,
+    /// returning whether there was any.  Entries already named `new` are
+    /// kept; see `dedup_hosts`.
+    pub fn rename_host(&mut self, old: &str, new: &str) -> bool {
+        let mut found = false;
+        for (name, _) in self.hosts.iter_mut().filter(|(name, _)| same_host(name, old)) {
+            *name = new.to_string();
+            found = true;
+        }
+        found
+    }
+
+    /// Drop every `machine` entry but the first for each host name, which
+    /// are the entries lookups find.
+    pub fn dedup_hosts(&mut self) {
+        let mut seen = BTreeSet::new();
+        self.hosts.retain(|(name, _)| seen.insert(name.to_ascii_lowercase()));
+    }
+
+    /// Remove every macro named `name`, returning whether there was any.
+    pub fn remove_macro(&mut self, name: &str) -> bool {
+        let len = self.macros.len();
+        self.macros.retain(|mac| mac.name != name);
+        self.macros.len() != len
+    }
+
+    /// Strip a leading `scheme://` and trailing slashes from the names of
+    /// the `machine` entries, so that an entry for `https://example.com/`
+    /// is found by a lookup of `example.com`.
+    pub fn normalize_hosts(&mut self) {
+        for (name, _) in &mut self.hosts {
+            let host = normalize_host(name);
+            if host.len() != name.len() {
+                *name = host.to_string();
+            }
+        }
+    }
+
+    /// Sort the `machine` entries by host name, ignoring ASCII case as
+    /// lookups do, and the macros by name, so that files with the same
+    /// entries in a different order serialize identically.  The sort is
+    /// stable: entries for the same host keep their relative order, and so
+    /// does which of them lookups find first.
+    pub fn sort(&mut self) {
+        self.hosts.sort_by_cached_key(|(host, _)| host.to_ascii_lowercase());
+        self.macros.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// Look up the `machine` entry for `host` on `port`.
+    ///
+    /// A file may list the same host several times with different ports.
+    /// The first entry for `host` whose port is `port` is returned; failing
+    /// that, the first entry for `host` without a port, which applies to
+    /// any port.  The `default` entry is not consulted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let input = "machine example.com login a port 21 machine example.com login b port 22";
+    /// let netrc = Netrc::parse_str(input, &Default::default()).unwrap();
+    /// assert_eq!(netrc.get_host_port("example.com", 22).unwrap().login, "b");
+    /// ```
+    pub fn get_host_port(&self, host: &str, port: u16) -> Option<&Machine> {
+        let mut any_port = None;
+        for (name, mach) in &self.hosts {
+            if !same_host(name, host) {
+                continue;
+            }
+            match mach.port {
+                Some(p) if p == port       => return Some(mach),
+                None if any_port.is_none() => any_port = Some(mach),
+                _                          => (),
+            }
+        }
+        any_port
+    }
+
+    /// Look up the machine to connect to `url` with, as an HTTP client
+    /// would: an entry for its host and port, else an entry for its host
+    /// without a port, else the `default` entry.  The port is that of
+    /// `url`, or the well-known port of its scheme for `http`, `https` and
+    /// `ftp`.  A `url` that cannot be read this way, such as one without
+    /// `scheme://` or with a port that is not a number, matches only the
+    /// `default` entry.
+    ///
+    /// `url` is anything viewed as a string, so a `url::Url` from the `url`
+    /// crate can be passed as is; this crate does not depend on it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let input = "machine example.com login a port 8080 machine example.com login b port 443";
+    /// let netrc = Netrc::parse_str(input, &Default::default()).unwrap();
+    /// let mach = netrc.machine_for_url("https://example.com/path").unwrap();
+    /// assert_eq!(mach.login, "b");
+    /// ```
+    pub fn machine_for_url<U: AsRef<str>>(&self, url: U) -> Option<&Machine> {
+        let mach = match url_host_port(url.as_ref()) {
+            Some((host, Some(port))) => self.get_host_port(host, port),
+            Some((host, None))       => self.get(host),
+            None                     => None,
+        };
+        mach.or(self.default.as_ref())
+    }
+
+    /// Find the macros whose body contains `substring`, such as the host
+    /// name a macro connects to, in the order they were defined.
+    pub fn find_macros_referencing(&self, substring: &str) -> Vec<&Macro> {
+        self.macros.iter().filter(|mac| mac.body.contains(substring)).collect()
+    }
+
+    /// Look up the machine for `host`: the first entry named `host`, then
+    /// with `options.wildcard` a matching wildcard entry, and failing both
+    /// with `options.use_default` the `default` entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::{LookupOptions, Netrc};
+    ///
+    /// let input = "machine *.example.com login foo default login bar";
+    /// let netrc = Netrc::parse_str(input, &Default::default()).unwrap();
+    /// let options = LookupOptions { wildcard: true, ..Default::default() };
+    /// let mach = netrc.machine_for("api.example.com", &options).unwrap();
+    /// assert_eq!(mach.login, "foo");
+    /// ```
+    pub fn machine_for(&self, host: &str, options: &LookupOptions) -> Option<&Machine> {
+        self.find_host(host, options).or(self.default_for(options))
+    }
+
+    /// Look up the machine for `host` like `machine_for` with the default
+    /// `LookupOptions`, failing with `Error::HostNotFound` if there is none.
+    pub fn get_required(&self, host: &str) -> Result<&Machine> {
+        self.machine_for(host, &Default::default())
+            .ok_or_else(|| Error::HostNotFound(host.to_string()))
+    }
+
+    /// Look up the first entry named `host`, falling back to the `default`
+    /// entry, as ftp and curl do.
+    pub fn find_machine(&self, host: &str) -> Option<&Machine> {
+        self.machine_for(host, &Default::default())
+    }
+
+    /// Like `find_machine`, but for changing the entry found in place.
+    pub fn find_machine_mut(&mut self, host: &str) -> Option<&mut Machine> {
+        match self.hosts.iter_mut().find(|(name, _)| same_host(name, host)) {
+            Some((_, mach)) => Some(mach),
+            None            => self.default.as_mut(),
+        }
+    }
+
+    fn default_for(&self, options: &LookupOptions) -> Option<&Machine> {
+        self.default.as_ref().filter(|_| options.use_default)
+    }
+
+    fn find_host(&self, host: &str, options: &LookupOptions) -> Option<&Machine> {
+        match self.get(host) {
+            None if options.wildcard => self.get_wildcard(host),
+            found                    => found,
+        }
+    }
+
+    fn get_wildcard(&self, host: &str) -> Option<&Machine> {
+        let mut best: Option<(usize, &Machine)> = None;
+        for (name, mach) in &self.hosts {
+            if !is_wildcard(name) || !wildcard_match(name, host) {
+                continue;
+            }
+            let len = name.chars().filter(|&c| c != '*' && c != '?').count();
+            if best.map_or(true, |(best_len, _)| len > best_len) {
+                best = Some((len, mach));
+            }
+        }
+        best.map(|(_, mach)| mach)
+    }
+
+    /// Resolve the credentials for `host` into an owned `Machine`, using
+    /// the default `LookupOptions`.  See `resolve_with`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let input = "machine example.com login foo default login bar password baz";
+    /// let netrc = Netrc::parse_str(input, &Default::default()).unwrap();
+    /// let mach = netrc.resolve("example.com").unwrap();
+    /// assert_eq!(mach.login, "foo");
+    /// assert_eq!(mach.password.unwrap().expose(), "baz");
+    /// ```
+    pub fn resolve(&self, host: &str) -> Option<Machine> {
+        self.resolve_with(host, &Default::default())
+    }
+
+    /// Resolve the credentials for `host` into an owned `Machine`.
+    ///
+    /// The entry found for `host` as by `machine_for` takes precedence; any
+    /// of its `password`, `account` and `port` that are unset are filled in
+    /// from the `default` entry.  If no entry matches `host`, the `default`
+    /// entry is returned as is, and `None` if there is no `default` either.
+    /// Without `options.use_default`, the `default` entry is ignored.
+    pub fn resolve_with(&self, host: &str, options: &LookupOptions) -> Option<Machine> {
+        match (self.find_host(host, options), self.default_for(options)) {
+            (Some(mach), Some(def)) => {
+                let mut mach = mach.clone();
+                if mach.password.is_none() {
+                    mach.password = def.password.clone();
+                }
+                if mach.account.is_none() {
+                    mach.account = def.account.clone();
+                }
+                if mach.port.is_none() && mach.port_name.is_none() {
+                    mach.port = def.port;
+                    mach.port_name = def.port_name.clone();
+                }
+                Some(mach)
+            }
+            (Some(mach), None) => Some(mach.clone()),
+            (None, def)        => def.cloned(),
+        }
+    }
+
+    /// Serialize to netrc syntax using the given format options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::{DefaultPosition, Netrc, NetrcFormat};
+    ///
+    /// let input = "machine example.com login foo default login bar";
+    /// let netrc = Netrc::parse_str(input, &Default::default()).unwrap();
+    /// let format = NetrcFormat { default_position: DefaultPosition::First, ..Default::default() };
+    /// assert_eq!(netrc.to_string_with(&format),
+    ///            "default login bar\nmachine example.com login foo\n");
+    /// ```
+    pub fn to_string_with(&self, format: &NetrcFormat) -> String {
+        let mut out = String::new();
+        self.write_with(&mut out, format).unwrap();
+        out
+    }
+
+    fn write_with<W: fmt::Write>(&self,
+                                 out: &mut W,
+                                 format: &NetrcFormat) -> fmt::Result {
+        let sep = match format.field_indent {
+            Some(indent) => format!("\n{:1$}", "", indent),
+            None         => " ".to_string(),
+        };
+        // Whether the next entry needs a blank line before it: not the
+        // first, nor one after a macro, whose terminating blank line
+        // already separates them.
+        let mut separate = false;
+        let mut next = |out: &mut W, is_macro: bool| {
+            if separate && format.blank_lines {
+                out.write_char('\n')?;
+            }
+            separate = !is_macro;
+            Ok(())
+        };
+        if format.default_position == DefaultPosition::First {
+            if let Some(ref mach) = self.default {
+                next(out, false)?;
+                writeln!(out, "default{}", MachineFields(mach, &sep))?;
+            }
+        }
+        for (name, mach) in &self.hosts {
+            next(out, false)?;
+            writeln!(out, "machine {}{}", Value(name), MachineFields(mach, &sep))?;
+        }
+        for mac in &self.macros {
+            next(out, true)?;
+            let rest_blank = mac.body.split('\n').next().is_some_and(|rest| rest.trim().is_empty());
+            if format.trim_macros && rest_blank {
+                writeln!(out, "macdef {}", mac.name)?;
+                for command in mac.body_trimmed().lines() {
+                    writeln!(out, "{}", command.trim())?;
+                }
+                out.write_char('\n')?;
+                continue;
+            }
+            // The body runs from the end of the `macdef' line up to and
+            // including the blank line that terminates it.
+            let sep = if mac.body.starts_with('\n') { "" } else { "\n" };
+            let body = format!("{}{}", sep, mac.body);
+            let term = if body.ends_with("\n\n") { "" }
+                       else if body.ends_with('\n') { "\n" }
+                       else { "\n\n" };
+            write!(out, "macdef {}{}{}", mac.name, body, term)?;
+        }
+        if format.default_position == DefaultPosition::Last {
+            if let Some(ref mach) = self.default {
+                next(out, false)?;
+                writeln!(out, "default{}", MachineFields(mach, &sep))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<&[u8]> for Netrc {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Netrc> {
+        Netrc::from_bytes(bytes)
+    }
+}
+
+/// The `machine` entries of a `Netrc` as host and machine, in order.  See
+/// `Netrc::machines`.
+#[derive(Clone, Debug)]
+pub struct Machines<'a>(core::slice::Iter<'a, Host>);
+
+impl<'a> Iterator for Machines<'a> {
+    type Item = (&'a str, &'a Machine);
+
+    fn next(&mut self) -> Option<(&'a str, &'a Machine)> {
+        self.0.next().map(|(host, mach)| (host.as_str(), mach))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Machines<'a> {
+    fn next_back(&mut self) -> Option<(&'a str, &'a Machine)> {
+        self.0.next_back().map(|(host, mach)| (host.as_str(), mach))
+    }
+}
+
+impl<'a> ExactSizeIterator for Machines<'a> {}
+
+/// Iterate over the `machine` entries, as `Netrc::machines` does.
+impl<'a> IntoIterator for &'a Netrc {
+    type Item = (&'a str, &'a Machine);
+    type IntoIter = Machines<'a>;
+
+    fn into_iter(self) -> Machines<'a> {
+        self.machines()
+    }
+}
+
+/// Take the `machine` entries, dropping `default` and the macros.
+impl IntoIterator for Netrc {
+    type Item = Host;
+    type IntoIter = alloc::vec::IntoIter<Host>;
+
+    fn into_iter(self) -> alloc::vec::IntoIter<Host> {
+        self.hosts.into_iter()
+    }
+}
+
+/// Collect `machine` entries, in order, into a `Netrc` without `default`
+/// or macros.
+impl FromIterator<Host> for Netrc {
+    fn from_iter<I: IntoIterator<Item = Host>>(hosts: I) -> Netrc {
+        Netrc { hosts: hosts.into_iter().collect(), ..Default::default() }
+    }
+}
+
+/// Append `machine` entries, leaving `default` and the macros alone.
+impl Extend<Host> for Netrc {
+    fn extend<I: IntoIterator<Item = Host>>(&mut self, hosts: I) {
+        self.hosts.extend(hosts);
+    }
+}
+
+/// Collect copies of borrowed `machine` entries, such as those of
+/// `Netrc::machines`.
+impl<'a> FromIterator<(&'a str, &'a Machine)> for Netrc {
+    fn from_iter<I: IntoIterator<Item = (&'a str, &'a Machine)>>(hosts: I) -> Netrc {
+        hosts.into_iter().map(|(host, mach)| (host.to_string(), mach.clone())).collect()
+    }
+}
+
+/// Append copies of borrowed `machine` entries, such as those of another
+/// `Netrc` iterated over by reference.
+impl<'a> Extend<(&'a str, &'a Machine)> for Netrc {
+    fn extend<I: IntoIterator<Item = (&'a str, &'a Machine)>>(&mut self, hosts: I) {
+        self.extend(hosts.into_iter().map(|(host, mach)| (host.to_string(), mach.clone())));
+    }
+}
+
+/// Serialize to netrc syntax with the default `NetrcFormat`.  With the
+/// default `ParseOptions`, the output parses back into an equal `Netrc`
+/// but for line numbers, unless an entry has a `port_name`, which needs
+/// `ParseOptions.port_names`, or `extras`, which need
+/// `ParseOptions.extra_fields`.  A macro body must end with a blank line
+/// and hold no other, as a parsed one does.
+impl fmt::Display for Netrc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_with(f, &Default::default())
+    }
+}
+
+/// Fields of a machine entry, each preceded by the separator, usually a
+/// space.
+struct MachineFields<'a>(&'a Machine, &'a str);
+
+impl<'a> fmt::Display for MachineFields<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let MachineFields(mach, sep) = *self;
+        if !mach.login.is_empty() {
+            write!(f, "{}login {}", sep, Value(&mach.login))?;
+        }
+        if let Some(ref password) = mach.password {
+            write!(f, "{}password {}", sep, Value(password.expose()))?;
+        }
+        if let Some(ref account) = mach.account {
+            write!(f, "{}account {}", sep, Value(account.expose()))?;
+        }
+        if let Some(port) = mach.port {
+            write!(f, "{}port {}", sep, port)?;
+        } else if let Some(ref name) = mach.port_name {
+            write!(f, "{}port {}", sep, name)?;
+        }
+        if let Some(ref protocol) = mach.protocol {
+            write!(f, "{}protocol {}", sep, Value(protocol))?;
+        }
+        for (keyword, value) in &mach.extras {
+            write!(f, "{}{} {}", sep, keyword, Value(value))?;
+        }
+        Ok(())
+    }
+}
+
+/// A value as written in netrc syntax: in double quotes, with escapes, if
+/// it is empty, has whitespace or starts with a quote, as read back with
+/// `ParseOptions.quoted_values`.
+struct Value<'a>(&'a str);
+
+impl<'a> fmt::Display for Value<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.0.is_empty() && !self.0.contains(char::is_whitespace)
+            && !self.0.starts_with('"') {
+            return f.write_str(self.0);
+        }
+        Quoted(self.0).fmt(f)
+    }
+}
+
+/// A value in double quotes, with escapes.
+struct Quoted<'a>(&'a str);
+
+impl<'a> fmt::Display for Quoted<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"'  => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                _    => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn parse_simple() {
+        let input = "machine example.com
+                             login test
+                             password p@ssw0rd
+                             port 42";
+        let input = BufReader::new(input.as_bytes());
+        let netrc = Netrc::parse(input).unwrap();
+        assert_eq!(netrc.hosts.len(), 1);
+        assert!(netrc.macros.is_empty());
+        let (ref name, ref mach) = netrc.hosts[0];
+        assert_eq!(name, "example.com");
+        assert_eq!(mach.login, "test");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "p@ssw0rd");
+        assert_eq!(mach.port, Some(42));
+        assert_eq!(mach.account, None);
+    }
+
+    #[test]
+    fn parse_macdef() {
+        let input = "machine host1.com login login1
+                     macdef uploadtest
+                            cd /pub/tests
+                            bin
+                            put filename.tar.gz
+                            quit
+
+                     machine host2.com login login2";
+        let input = BufReader::new(input.as_bytes());
+        let netrc = Netrc::parse(input).unwrap();
+        assert_eq!(netrc.hosts.len(), 2);
+        for host in netrc.hosts.iter().enumerate() {
+            let (i, (name, mach)) = host;
+            let i = i + 1;
+            assert_eq!(name, &format!("host{}.com", i));
+            assert_eq!(mach.login, format!("login{}", i));
+        }
+        assert_eq!(netrc.macros.len(), 1);
+        let mac = &netrc.macros[0];
+        assert_eq!(mac.name, "uploadtest");
+        assert_eq!(mac.body.trim(), "cd /pub/tests
+                            bin
+                            put filename.tar.gz
+                            quit");
+    }
+
+    #[test]
+    fn parse_default() {
+        let input = "machine example.com login test
+                     default login def";
+        let input = BufReader::new(input.as_bytes());
+        let netrc = Netrc::parse(input).unwrap();
+        assert_eq!(netrc.hosts.len(), 1);
+        let (ref name, ref mach) = netrc.hosts[0];
+        assert_eq!(name, "example.com");
+        assert_eq!(mach.login, "test");
+        let def_mach = netrc.default.unwrap();
+        assert_eq!(def_mach.login, "def");
+    }
+
+    #[test]
+    fn parse_error_unknown_entry() {
+        let input = "machine foobar.com
+                             foo";
+        let input = BufReader::new(input.as_bytes());
+        let err = Netrc::parse(input).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown entry `foo' at line 2");
+        match err {
+            Error::UnknownEntry { keyword, line } => {
+                assert_eq!(keyword, "foo");
+                assert_eq!(line, 2);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_error_unexpected_eof() {
+        let input = "machine foobar.com
+                             password quux
+                             login";
+        let input = BufReader::new(input.as_bytes());
+        match Netrc::parse(input).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Missing value for `login'");
+                assert_eq!(line, 3);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_error_no_machine() {
+        let input = "password quux login foo";
+        let input = BufReader::new(input.as_bytes());
+        match Netrc::parse(input).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "No machine defined for password");
+                assert_eq!(line, 1);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_error_port() {
+        let input = "machine foo.com login bar port quux";
+        let input = BufReader::new(input.as_bytes());
+        match Netrc::parse(input).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Unable to parse port number `quux'");
+                assert_eq!(line, 1);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn format_default_last() {
+        let input = "default login anonymous password me@example.com
+                     machine example.com login test port 21";
+        let input = BufReader::new(input.as_bytes());
+        let netrc = Netrc::parse(input).unwrap();
+        let format = NetrcFormat { default_position: DefaultPosition::Last, ..Default::default() };
+        let expected = "machine example.com login test port 21
+default login anonymous password me@example.com
+";
+        assert_eq!(netrc.to_string_with(&format), expected);
+        assert_eq!(netrc.to_string(), expected);
+    }
+
+    #[test]
+    fn format_default_first() {
+        let input = "machine example.com login test
+                     macdef init
+                            bin
+
+                     default login anonymous";
+        let input = BufReader::new(input.as_bytes());
+        let netrc = Netrc::parse(input).unwrap();
+        let format = NetrcFormat { default_position: DefaultPosition::First, ..Default::default() };
+        let output = netrc.to_string_with(&format);
+        assert!(output.starts_with("default login anonymous\n"));
+        let netrc = Netrc::parse(BufReader::new(output.as_bytes())).unwrap();
+        assert_eq!(netrc.hosts.len(), 1);
+        assert_eq!(netrc.macros.len(), 1);
+        assert_eq!(netrc.default.unwrap().login, "anonymous");
+    }
+
+    #[test]
+    fn merge_strategies() {
+        let system = "machine a.com login sys-a
+                      machine b.com login sys-b port 21
+                      default login anon
+                      macdef init
+                      bin
+";
+        let user = "machine A.COM login me-a
+                    machine b.com login me-b
+                    macdef init
+                    ascii
+
+                    macdef other
+                    bin
+";
+        let merged = |strategy| {
+            let mut netrc = Netrc::parse(system.as_bytes()).unwrap();
+            let user = Netrc::parse(user.as_bytes()).unwrap();
+            netrc.merge(user, strategy).map(|()| netrc)
+        };
+        let netrc = merged(MergeStrategy::KeepExisting).unwrap();
+        let logins: Vec<_> = netrc.machines().map(|(_, mach)| mach.login.as_str()).collect();
+        assert_eq!(logins, ["sys-a", "sys-b", "me-b"]);
+        assert_eq!(netrc.default.as_ref().unwrap().login, "anon");
+        let bodies: Vec<_> = netrc.macros().map(|mac| mac.body_trimmed().trim()).collect();
+        assert_eq!(bodies, ["bin", "bin"]);
+
+        let netrc = merged(MergeStrategy::PreferOther).unwrap();
+        let logins: Vec<_> = netrc.machines().map(|(_, mach)| mach.login.as_str()).collect();
+        assert_eq!(logins, ["me-a", "sys-b", "me-b"]);
+        assert_eq!(netrc.hosts[0].0, "A.COM");
+        let bodies: Vec<_> = netrc.macros().map(|mac| mac.body_trimmed().trim()).collect();
+        assert_eq!(bodies, ["ascii", "bin"]);
+
+        match merged(MergeStrategy::ErrorOnConflict).unwrap_err() {
+            Error::MergeConflict(what) => assert_eq!(what, "an entry for `A.COM'"),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        let mut netrc = Netrc::parse(system.as_bytes()).unwrap();
+        let other = Netrc::parse("default login me".as_bytes()).unwrap();
+        let err = netrc.merge(other, MergeStrategy::ErrorOnConflict).unwrap_err();
+        assert_eq!(err.to_string(), "Both netrcs have `default'");
+        assert_eq!(netrc.to_string(), Netrc::parse(system.as_bytes()).unwrap().to_string());
+    }
+
+    #[test]
+    fn iterate_entries() {
+        let input = "machine a.com login a
+                     default login anon
+                     macdef init
+                     bin
+
+                     machine b.com login b";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let mut entries = Vec::new();
+        for (host, mach) in &netrc {
+            entries.push((host, mach.login.as_str()));
+        }
+        assert_eq!(entries, [("a.com", "a"), ("b.com", "b")]);
+        assert_eq!(netrc.machines().len(), 2);
+        assert_eq!(netrc.machines().next_back().unwrap().0, "b.com");
+        assert_eq!(netrc.machine_names().rev().collect::<Vec<_>>(), ["b.com", "a.com"]);
+        assert_eq!(netrc.macros().map(|mac| mac.name.as_str()).collect::<Vec<_>>(), ["init"]);
+        let hosts: Vec<Host> = netrc.into_iter().collect();
+        assert_eq!(hosts[1].0, "b.com");
+    }
+
+    #[test]
+    fn format_canonical() {
+        let input = "machine b.com   password pb login b
+                     default login anonymous
+                     macdef init  
+                        cd /pub  
+                       bin
+
+                     machine a.com login \"a b\" port 21";
+        let options = ParseOptions { quoted_values: true, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        let expected = "machine b.com
+    login b
+    password pb
+
+machine a.com
+    login \"a b\"
+    port 21
+
+macdef init
+cd /pub
+bin
+
+default
+    login anonymous
+";
+        let output = netrc.to_string_with(&NetrcFormat::canonical());
+        assert_eq!(output, expected);
+        let reparsed = Netrc::parse_with_options(output.as_bytes(), &options).unwrap();
+        assert_eq!(reparsed.to_string_with(&NetrcFormat::canonical()), expected);
+    }
+
+    #[test]
+    fn parse_quoted_tab() {
+        let input = "machine example.com login \"te st\"
+                             password \"p@ss\tw0rd\"\taccount \"acct";
+        let input = BufReader::new(input.as_bytes());
+        let netrc = Netrc::parse_accepting_tabs_in_values(input).unwrap();
+        let (ref name, ref mach) = netrc.hosts[0];
+        assert_eq!(name, "example.com");
+        assert_eq!(mach.login, "te st");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "p@ss\tw0rd");
+        assert_eq!(mach.account.as_ref().unwrap().expose(), "acct");
+    }
+
+    #[test]
+    fn error_display() {
+        let input = BufReader::new("machine foo.com port quux".as_bytes());
+        let err = Netrc::parse(input).unwrap_err();
+        assert_eq!(err.to_string(),
+                   "Unable to parse port number `quux' at line 1");
+        assert!(std::error::Error::source(&err).is_none());
+    }
+
+    #[test]
+    fn parse_macdef_lines() {
+        let input = "machine host1.com login login1
+                     macdef uploadtest
+                            cd /pub/tests
+                            quit
+
+                     macdef last
+                            bin";
+        let input = BufReader::new(input.as_bytes());
+        let netrc = Netrc::parse(input).unwrap();
+        assert_eq!(netrc.macros[0].lines, Some((2, 5)));
+        assert_eq!(netrc.macros[1].lines, Some((6, 7)));
+    }
+
+    #[test]
+    fn resolve_partial_override() {
+        let input = "machine example.com login test port 42
+                     default login def password p@ss account acct port 21";
+        let input = BufReader::new(input.as_bytes());
+        let netrc = Netrc::parse(input).unwrap();
+        let mach = netrc.resolve("example.com").unwrap();
+        assert_eq!(mach.login, "test");
+        assert_eq!(mach.password.unwrap().expose(), "p@ss");
+        assert_eq!(mach.account.unwrap().expose(), "acct");
+        assert_eq!(mach.port, Some(42));
+    }
+
+    #[test]
+    fn resolve_fallback() {
+        let input = "machine example.com login test
+                     default login def password p@ss";
+        let input = BufReader::new(input.as_bytes());
+        let netrc = Netrc::parse(input).unwrap();
+        let mach = netrc.resolve("other.com").unwrap();
+        assert_eq!(mach.login, "def");
+        assert_eq!(mach.password.unwrap().expose(), "p@ss");
+
+        let input = BufReader::new("machine example.com login test".as_bytes());
+        let netrc = Netrc::parse(input).unwrap();
+        assert!(netrc.resolve("other.com").is_none());
+        assert!(netrc.resolve("example.com").unwrap().password.is_none());
+    }
+
+    #[test]
+    fn parse_infer_port_from_protocol() {
+        let input = "machine example.com login test protocol https
+                     machine ftp.example.com protocol ftp port 2121
+                     machine other.example.com protocol gopher
+                     default protocol http";
+        let options = ParseOptions {
+            infer_port_from_protocol: true,
+            ..Default::default()
+        };
+        let netrc = Netrc::parse_with_options(BufReader::new(input.as_bytes()),
+                                              &options).unwrap();
+        assert_eq!(netrc.hosts[0].1.protocol.as_ref().unwrap(), "https");
+        assert_eq!(netrc.hosts[0].1.port, Some(443));
+        assert_eq!(netrc.hosts[1].1.port, Some(2121));
+        assert_eq!(netrc.hosts[2].1.port, None);
+        assert_eq!(netrc.default.unwrap().port, Some(80));
+
+        let netrc = Netrc::parse(BufReader::new(input.as_bytes())).unwrap();
+        assert_eq!(netrc.hosts[0].1.port, None);
+    }
+
+    #[test]
+    fn parse_concatenated_stream() {
+        let input = "machine a.com login a
+                     macdef init
+                            bin
+---
+                     machine b.com login b
+                     default login def
+---
+                     machine c.com port x";
+        let err = Netrc::parse_concatenated_stream(input.as_bytes(), "---")
+            .unwrap_err();
+        match err {
+            Error::Parse { line, .. } => assert_eq!(line, 8),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        let input = &input[..input.rfind("---").unwrap()];
+        let sections = Netrc::parse_concatenated_stream(input.as_bytes(), "---")
+            .unwrap();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].hosts[0].0, "a.com");
+        assert_eq!(sections[0].macros[0].body.trim(), "bin");
+        assert!(sections[0].default.is_none());
+        assert_eq!(sections[1].hosts[0].0, "b.com");
+        assert_eq!(sections[1].default.as_ref().unwrap().login, "def");
+    }
+
+    #[test]
+    fn parse_bom() {
+        let input = b"\xef\xbb\xbfmachine example.com login test\n";
+        let netrc = Netrc::parse(&input[..]).unwrap();
+        assert_eq!(netrc.hosts[0].0, "example.com");
+        assert_eq!(netrc.hosts[0].1.login, "test");
+
+        let input = "machine example.com login test\n\u{feff}machine";
+        match Netrc::parse(input.as_bytes()).unwrap_err() {
+            Error::UnknownEntry { keyword, line } => {
+                assert_eq!(keyword, "\u{feff}machine");
+                assert_eq!(line, 2);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn get_host_port_duplicates() {
+        let input = "machine example.com login any
+                     machine example.com login ftp port 21
+                     machine example.com login ssh port 22
+                     machine other.com login other port 22";
+        let input = BufReader::new(input.as_bytes());
+        let netrc = Netrc::parse(input).unwrap();
+        assert_eq!(netrc.hosts.len(), 4);
+        assert_eq!(netrc.get("example.com").unwrap().login, "any");
+        assert_eq!(netrc.get_host_port("example.com", 21).unwrap().login, "ftp");
+        assert_eq!(netrc.get_host_port("example.com", 22).unwrap().login, "ssh");
+        assert_eq!(netrc.get_host_port("example.com", 80).unwrap().login, "any");
+        assert!(netrc.get_host_port("other.com", 80).is_none());
+        assert!(netrc.get("missing.com").is_none());
+    }
+
+    #[test]
+    fn parse_error_line_length() {
+        let options = ParseOptions { max_line_length: 16, ..Default::default() };
+        let input = "machine a.com\nlogin abcdefghijklmnopqrstuvwxyz\n";
+        let err = Netrc::parse_with_options(input.as_bytes(), &options)
+            .unwrap_err();
+        match err {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Line exceeds the maximum length of 16 bytes");
+                assert_eq!(line, 2);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        let input = "machine a.com\nlogin abcdefghi\n";
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts[0].1.login, "abcdefghi");
+
+        let options = ParseOptions { max_line_length: usize::MAX, ..Default::default() };
+        let input = "machine a.com\nlogin abcdefghijklmnopqrstuvwxyz\n";
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts[0].1.login, "abcdefghijklmnopqrstuvwxyz");
+        let options = ParseOptions { encoding: Encoding::Latin1, ..options };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts.len(), 1);
+    }
+
+    #[test]
+    fn parse_error_limits() {
+        let options = ParseOptions {
+            max_hosts: 1,
+            max_macro_length: 8,
+            ..Default::default()
+        };
+        let input = "machine a.com machine b.com";
+        match Netrc::parse_with_options(input.as_bytes(), &options).unwrap_err() {
+            Error::Parse { msg, .. } =>
+                assert_eq!(msg, "Too many machine entries (limit 1)"),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        let input = "macdef m\nbin\nbin\nbin\n\n";
+        match Netrc::parse_with_options(input.as_bytes(), &options).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Macro exceeds the maximum length of 8 bytes");
+                assert_eq!(line, 1);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_port_names() {
+        let input = "machine a.com port 443
+                     machine b.com port https
+                     machine c.com port imap-s2";
+        let options = ParseOptions { port_names: true, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts[0].1.port, Some(443));
+        assert_eq!(netrc.hosts[0].1.port_name, None);
+        assert_eq!(netrc.hosts[1].1.port, None);
+        assert_eq!(netrc.hosts[1].1.port_name.as_ref().unwrap(), "https");
+        assert_eq!(netrc.hosts[2].1.port_name.as_ref().unwrap(), "imap-s2");
+        assert!(netrc.to_string().contains("machine b.com port https\n"));
+
+        for port in &["70000", "ht/tp", "42-"] {
+            let input = format!("machine a.com port {}", port);
+            match Netrc::parse_with_options(input.as_bytes(), &options).unwrap_err() {
+                Error::Parse { msg, .. } => assert_eq!(
+                    msg, format!("Unable to parse port number `{}'", port)),
+                e => panic!("Wrong Error type: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_range() {
+        let blob = b"HEADER\0machine example.com login test\0TRAILER";
+        let netrc = Netrc::parse_range(blob, 7..37).unwrap();
+        assert_eq!(netrc.hosts[0].0, "example.com");
+        assert_eq!(netrc.hosts[0].1.login, "test");
+        match Netrc::parse_range(blob, 30..100).unwrap_err() {
+            Error::Io(e) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn find_macros_referencing() {
+        let input = "macdef up
+                            open files.example.com
+                            put a.tar.gz
+
+                     macdef down
+                            open mirror.example.org
+
+                     macdef both
+                            open files.example.com
+                            open mirror.example.org
+";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let names = |s: &str| netrc.find_macros_referencing(s).iter()
+            .map(|mac| mac.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names("files.example.com"), ["up", "both"]);
+        assert_eq!(names("mirror.example.org"), ["down", "both"]);
+        assert!(names("other.example.net").is_empty());
+    }
+
+    #[test]
+    fn macro_body_trimmed() {
+        let input = "macdef first
+cd /pub
+bin
+
+machine example.com login test
+macdef last
+cd /tmp
+quit";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.macros[0].body, "\ncd /pub\nbin\n\n");
+        assert_eq!(netrc.macros[0].body_trimmed(), "cd /pub\nbin");
+        assert_eq!(netrc.macros[1].body, "\ncd /tmp\nquit\n\n");
+        assert_eq!(netrc.macros[1].body_trimmed(), "cd /tmp\nquit");
+        assert_eq!(netrc.hosts[0].0, "example.com");
+
+        let netrc = Netrc::parse("macdef empty\n\n".as_bytes()).unwrap();
+        assert_eq!(netrc.macros[0].body_trimmed(), "");
+    }
+
+    #[test]
+    fn machine_metadata_only() {
+        let input = "machine example.com login test password p@ss account acct port 21";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let mach = netrc.hosts[0].1.metadata_only();
+        assert_eq!(mach.login, "test");
+        assert_eq!(mach.port, Some(21));
+        assert_eq!(mach.password, None);
+        assert_eq!(mach.account, None);
+        assert_eq!(netrc.hosts[0].1.password.as_ref().unwrap().expose(), "p@ss");
+    }
+
+    #[test]
+    fn lookup_wildcard() {
+        let input = "machine *.example.com login wild
+                     machine *.api.example.com login narrow password p@ss
+                     machine www.example.com login exact
+                     default login def password dflt";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let options = LookupOptions { wildcard: true, ..Default::default() };
+        let login = |host: &str| netrc.machine_for(host, &options).unwrap().login.clone();
+        assert_eq!(login("api.example.com"), "wild");
+        assert_eq!(login("API.Example.COM"), "wild");
+        assert_eq!(login("v1.api.example.com"), "narrow");
+        assert_eq!(login("www.example.com"), "exact");
+        assert_eq!(login("example.com"), "def");
+        assert_eq!(login("example.com.evil.net"), "def");
+        assert_eq!(login("evilexample.com"), "def");
+
+        let plain = netrc.machine_for("api.example.com", &Default::default());
+        assert_eq!(plain.unwrap().login, "def");
+        let mach = netrc.resolve_with("api.example.com", &options).unwrap();
+        assert_eq!(mach.login, "wild");
+        assert_eq!(mach.password.unwrap().expose(), "dflt");
+    }
+
+    #[test]
+    fn lookup_wildcard_glob() {
+        let input = "machine db-?.corp login db
+                     machine *-staging.*.corp login staging
+                     machine *.corp login corp";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let options = LookupOptions { wildcard: true, use_default: false };
+        let login = |host: &str| netrc.machine_for(host, &options).map(|m| m.login.as_str());
+        assert_eq!(login("db-1.corp"), Some("db"));
+        assert_eq!(login("db-12.corp"), Some("corp"));
+        assert_eq!(login("web-staging.eu.corp"), Some("staging"));
+        assert_eq!(login("-staging.eu.corp"), Some("corp"));
+        assert_eq!(login("corp"), None);
+
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("a*", "a"));
+        assert!(!wildcard_match("a?c", "ac"));
+        assert!(wildcard_match("*.B.com", "a.b.COM"));
+    }
+
+    #[test]
+    fn parse_host_filter() {
+        let input = "machine a.example.com login a
+                     machine other.net login o password secret port 21
+                     machine b.example.com login b
+                     default login def";
+        let netrc = Netrc::parse_with_host_filter(
+            input.as_bytes(), |host| host.ends_with(".example.com")).unwrap();
+        let names: Vec<_> = netrc.hosts.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["a.example.com", "b.example.com"]);
+        assert_eq!(netrc.hosts[1].1.login, "b");
+        assert_eq!(netrc.hosts[1].1.password, None);
+        assert_eq!(netrc.default.unwrap().login, "def");
+
+        let input = "machine other.net port quux";
+        let err = Netrc::parse_with_host_filter(input.as_bytes(), |_| false);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn parse_duplicate_host() {
+        let input = "machine a.com login first
+                     machine b.com login b
+                     machine a.com login second password p@ss
+                     machine a.com login third";
+        let parse = |policy| {
+            let options = ParseOptions { on_duplicate_host: policy, ..Default::default() };
+            Netrc::parse_with_options(input.as_bytes(), &options)
+        };
+        let hosts = |policy| {
+            let netrc: Netrc = parse(policy).unwrap();
+            netrc.hosts.iter()
+                .map(|(name, mach)| format!("{}:{}", name, mach.login))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hosts(DuplicateHost::KeepAll),
+                   ["a.com:first", "b.com:b", "a.com:second", "a.com:third"]);
+        assert_eq!(hosts(DuplicateHost::KeepFirst), ["a.com:first", "b.com:b"]);
+        assert_eq!(hosts(DuplicateHost::KeepLast), ["b.com:b", "a.com:third"]);
+        match parse(DuplicateHost::Error).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Duplicate machine `a.com'");
+                assert_eq!(line, 3);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn get_required() {
+        let input = "machine example.com login test";
+        let mut netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.get_required("example.com").unwrap().login, "test");
+        match netrc.get_required("other.com").unwrap_err() {
+            Error::HostNotFound(host) => assert_eq!(host, "other.com"),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        netrc.default = Some(Machine { login: "def".to_string(), ..Default::default() });
+        assert_eq!(netrc.get_required("other.com").unwrap().login, "def");
+    }
+
+    #[test]
+    fn upsert_machine() {
+        let mut netrc = Netrc::default();
+        let mach = |login: &str| Machine { login: login.to_string(), ..Default::default() };
+        netrc.upsert_machine("a.com", mach("a"));
+        netrc.upsert_machine("b.com", mach("b"));
+        netrc.upsert_machine("c.com", mach("c"));
+        netrc.upsert_machine("b.com", mach("b2"));
+        let hosts: Vec<_> = netrc.hosts.iter()
+            .map(|(name, mach)| format!("{}:{}", name, mach.login))
+            .collect();
+        assert_eq!(hosts, ["a.com:a", "b.com:b2", "c.com:c"]);
+    }
 
-#[derive(Debug, Default)]
-pub struct Netrc {
-    pub hosts: Vec<Host>,
-    pub default: Option<Machine>,
-    pub macros: Vec<Macro>,
-}
+    #[test]
+    fn sort() {
+        let a = "machine b.com login b1
+                 macdef z
+                 quit
 
-#[derive(Debug)]
-pub enum Error {
-    Io(std::io::Error),
-    Parse(String, usize),
-}
+                 machine a.com login a
+                 machine b.com login b2
+                 macdef y
+                 bin
 
-pub type Result<A> = std::result::Result<A, Error>;
+                 default login def";
+        let b = "default login def
+                 machine a.com login a
+                 machine b.com login b1
+                 machine b.com login b2
+                 macdef y
+                 bin
 
-impl Netrc {
-    /// Parse a `Netrc` object from byte stream.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use netrc::Netrc;
-    /// use std::io::Cursor;
-    ///
-    /// let input: Cursor<&[u8]> =
-    ///   Cursor::new(b"machine example.com login foo password bar");
-    /// let netrc = Netrc::parse(input).unwrap();
-    /// ```
-    pub fn parse<A: BufRead>(buf: A) -> Result<Netrc> {
-        let mut netrc: Netrc = Default::default();
-        let mut lexer = Lexer::new(buf);
-        let mut current_machine = MachineRef::Nothing;
-        loop {
-            match lexer.next_word() {
-                None         => break,
-                Some(Err(e)) => return Err(e),
-                Some(Ok(w))  => current_machine = try! {
-                    netrc.parse_entry(&mut lexer, &w, current_machine)
-                },
+                 macdef z
+                 quit
+";
+        let mut a = Netrc::parse(a.as_bytes()).unwrap();
+        let mut b = Netrc::parse(b.as_bytes()).unwrap();
+        a.sort();
+        b.sort();
+        assert_eq!(a.hosts[1].1.login, "b1");
+        assert_eq!(a.hosts[2].1.login, "b2");
+        assert_eq!(a.to_string(), b.to_string());
+
+        let input = "machine b.com login b1
+                     machine A.com login a
+                     machine B.COM login b2
+                     machine a.com login b3";
+        let mut netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let first = netrc.get("b.com").unwrap().login.clone();
+        netrc.sort();
+        let logins: Vec<_> = netrc.machines().map(|(_, mach)| mach.login.as_str()).collect();
+        assert_eq!(logins, ["a", "b3", "b1", "b2"]);
+        assert_eq!(netrc.get("b.com").unwrap().login, first);
+    }
+
+    #[test]
+    fn parse_strict_default_shadows_machine() {
+        let input = "machine a.com login a
+                     default
+                     login foo password bar
+                     machine example.com
+                     macdef init
+                     bin
+";
+        let options = ParseOptions { strict: true, ..Default::default() };
+        match Netrc::parse_with_options(input.as_bytes(), &options).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert!(msg.starts_with("Machine `example.com' has no fields"), "{}", msg);
+                assert_eq!(line, 4);
             }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        assert!(Netrc::parse(input.as_bytes()).is_ok());
+
+        let input = "default login foo machine example.com login bar";
+        assert!(Netrc::parse_with_options(input.as_bytes(), &options).is_ok());
+    }
+
+    #[test]
+    fn parse_strict_whitespace() {
+        let input = "machine example.com login test password p@ss\u{a0}w0rd\u{2003}x";
+        let options = ParseOptions { strict_whitespace: true, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        let password = netrc.hosts[0].1.password.as_ref().unwrap();
+        assert_eq!(password.expose(), "p@ss\u{a0}w0rd\u{2003}x");
+
+        match Netrc::parse(input.as_bytes()).unwrap_err() {
+            Error::UnknownEntry { keyword, .. } => assert_eq!(keyword, "w0rd"),
+            e => panic!("Wrong Error type: {:?}", e),
         }
-        Ok(netrc)
-    }
-
-    fn parse_entry<A: BufRead>(&mut self,
-                               lexer: &mut Lexer<A>,
-                               item: &str,
-                               current_machine: MachineRef) -> Result<MachineRef> {
-        macro_rules! with_current_machine {
-            ($entry: expr, $machine: ident, $body: block) => {
-                match self.find_machine(&current_machine) {
-                    Some($machine) => {
-                        $body;
-                        Ok(current_machine)
-                    }
-                    None =>
-                        Err(Error::Parse(format!("No machine defined for {}",
-                                                 $entry),
-                                         lexer.lnum)),
+    }
+
+    #[test]
+    fn parse_machine_lines() {
+        let input = "machine a.com login a
+
+                     machine b.com
+                       login b
+                     default login def";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.line_of("a.com"), Some(1));
+        assert_eq!(netrc.line_of("b.com"), Some(3));
+        assert_eq!(netrc.line_of("c.com"), None);
+        assert_eq!(netrc.default.unwrap().line, Some(5));
+    }
+
+    #[test]
+    fn lookup_all() {
+        let input = "machine example.com login a
+                     machine other.com login o
+                     machine example.com login b account acct
+                     default login def";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let logins = |include_default| {
+            netrc.lookup_all("example.com", include_default).iter()
+                .map(|mach| mach.login.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(logins(false), ["a", "b"]);
+        assert_eq!(logins(true), ["a", "b", "def"]);
+        assert!(netrc.lookup_all("none.com", false).is_empty());
+    }
+
+    #[test]
+    fn parse_strict_empty_machine() {
+        let options = ParseOptions { strict: true, ..Default::default() };
+        let parse = |input: &str| Netrc::parse_with_options(input.as_bytes(), &options);
+        for (input, expected, expected_line) in [
+            ("machine a.com\nmachine b.com login b", "Machine `a.com' has no fields", 1),
+            ("machine a.com login a\nmachine b.com", "Machine `b.com' has no fields", 2),
+            ("machine\nmachine b.com login b", "Missing host name for `machine'", 2),
+        ] {
+            match parse(input).unwrap_err() {
+                Error::Parse { msg, line } => {
+                    assert_eq!(msg, expected);
+                    assert_eq!(line, expected_line);
                 }
+                e => panic!("Wrong Error type: {:?}", e),
             }
         }
+        assert!(Netrc::parse("machine a.com\nmachine b.com login b".as_bytes()).is_ok());
+        assert!(parse("machine a.com port 21\ndefault").is_ok());
+    }
 
-        match item {
-            "machine" => {
-                let host_name = try!(lexer.next_word_or_err());
-                self.hosts.push((host_name, Default::default()));
-                Ok(MachineRef::Host(self.hosts.len() - 1))
-            }
-            "default" => {
-                self.default = Some(Default::default());
-                Ok(MachineRef::Default)
-            }
-            "login" => with_current_machine!("login", m, {
-                m.login = try!(lexer.next_word_or_err());
-            }),
-            "password" => with_current_machine!("password", m, {
-                m.password = Some(try!(lexer.next_word_or_err()));
-            }),
-            "account" => with_current_machine!("account", m, {
-                m.account = Some(try!(lexer.next_word_or_err()));
-            }),
-            "port" => with_current_machine!("port", m, {
-                let port = try!(lexer.next_word_or_err());
-                match port.parse() {
-                    Ok(port) => m.port = Some(port),
-                    Err(_)   => {
-                        let msg = format!("Unable to parse port number `{}'",
-                                          port);
-                        return Err(Error::Parse(msg, lexer.lnum));
-                    }
-                }
-            }),
-            "macdef" => {
-                let name = try!(lexer.next_word_or_err());
-                let cmds = try!(lexer.next_subcommands());
-                self.macros.push((name, cmds));
-                Ok(MachineRef::Nothing)
+    #[test]
+    fn parse_strict_duplicate_field() {
+        let input = "machine a.com login a password p
+                     login b";
+        let options = ParseOptions { strict: true, ..Default::default() };
+        match Netrc::parse_with_options(input.as_bytes(), &options).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Duplicate `login' in the same entry");
+                assert_eq!(line, 2);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        assert_eq!(Netrc::parse(input.as_bytes()).unwrap().hosts[0].1.login, "b");
+
+        let input = "machine a.com login a default login b";
+        assert!(Netrc::parse_with_options(input.as_bytes(), &options).is_ok());
+    }
+
+    /// A reader yielding `data`, then failing.
+    struct FailingReader<'a> {
+        data: &'a [u8],
+    }
+
+    impl<'a> Read for FailingReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.data.is_empty() {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, "disk on fire"));
             }
-            _ => Err(Error::Parse(format!("Unknown entry `{}'", item),
-                                  lexer.lnum)),
+            self.data.read(buf)
         }
     }
 
-    fn find_machine(&mut self,
-                    reference: &MachineRef) -> Option<&mut Machine> {
-        match *reference {
-            MachineRef::Nothing => None,
-            MachineRef::Default => self.default.as_mut(),
-            MachineRef::Host(n) => Some(&mut self.hosts[n].1),
+    #[test]
+    fn parse_error_io() {
+        let reader = FailingReader { data: b"machine example.com login test\n" };
+        match Netrc::parse(BufReader::new(reader)).unwrap_err() {
+            Error::Io(e) => assert_eq!(e.to_string(), "disk on fire"),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+
+        let reader = FailingReader { data: b"machine example.com port quux\n" };
+        match Netrc::parse(BufReader::new(reader)).unwrap_err() {
+            Error::Parse { line, .. } => assert_eq!(line, 1),
+            e => panic!("Wrong Error type: {:?}", e),
         }
     }
-}
 
-enum MachineRef {
-    Nothing,
-    Default,
-    Host(usize),
-}
+    #[test]
+    fn parse_macdef_at_eof() {
+        let blank = Netrc::parse("macdef init\ncd /pub\nbin\n\n".as_bytes()).unwrap();
+        for input in ["macdef init\ncd /pub\nbin", "macdef init\ncd /pub\nbin\n"] {
+            let netrc = Netrc::parse(input.as_bytes()).unwrap();
+            assert_eq!(netrc.macros[0].body, blank.macros[0].body);
+            assert_eq!(netrc.macros[0].body_trimmed(), "cd /pub\nbin");
+            assert_eq!(netrc.macros[0].lines, Some((1, 3)));
+            assert_eq!(netrc.to_string(), blank.to_string());
+        }
+        let netrc = Netrc::parse("macdef empty".as_bytes()).unwrap();
+        assert_eq!(netrc.macros[0].body, "\n\n");
+    }
 
-struct Tokens {
-    buf: String,
-    cur: usize,
-}
+    #[test]
+    fn collect_hosts() {
+        let mach = |login: &str| Machine { login: login.to_string(), ..Default::default() };
+        let mut netrc: Netrc = ["b.com", "a.com"].iter()
+            .map(|&name| (name.to_string(), mach(name)))
+            .collect();
+        netrc.default = Some(mach("def"));
+        netrc.extend(vec![("c.com".to_string(), mach("c")), ("a.com".to_string(), mach("a2"))]);
+        let hosts: Vec<_> = netrc.hosts.iter()
+            .map(|(name, mach)| format!("{}:{}", name, mach.login))
+            .collect();
+        assert_eq!(hosts, ["b.com:b.com", "a.com:a.com", "c.com:c", "a.com:a2"]);
+        assert_eq!(netrc.default.unwrap().login, "def");
+        assert!(netrc.macros.is_empty());
 
-impl Tokens {
-    fn new(buf: String) -> Tokens {
-        Tokens { buf: buf, cur: 0 }
+        let other = Netrc::parse("machine d.com login d machine e.com login e".as_bytes()).unwrap();
+        let mut merged: Netrc = other.machines().filter(|&(host, _)| host == "d.com").collect();
+        merged.extend(&other);
+        let hosts: Vec<_> = merged.machine_names().collect();
+        assert_eq!(hosts, ["d.com", "d.com", "e.com"]);
     }
 
-    fn empty() -> Tokens {
-        Tokens::new("".to_string())
+    #[test]
+    fn lookup_without_default() {
+        let input = "machine example.com login test
+                     default login def password p@ss";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let options = LookupOptions { use_default: false, ..Default::default() };
+        assert_eq!(netrc.machine_for("other.com", &Default::default()).unwrap().login, "def");
+        assert!(netrc.machine_for("other.com", &options).is_none());
+        assert!(netrc.resolve_with("other.com", &options).is_none());
+        let mach = netrc.resolve_with("example.com", &options).unwrap();
+        assert_eq!(mach.login, "test");
+        assert!(mach.password.is_none());
     }
 
-    fn remaining(&self) -> &str {
-        &self.buf[self.cur..]
+    #[test]
+    fn remove_host_and_macro() {
+        let input = "machine a.com login a1
+                     machine b.com login b
+                     machine a.com login a2
+                     macdef init
+                     bin
+
+                     default login def";
+        let mut netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert!(netrc.remove_host("a.com"));
+        assert!(!netrc.remove_host("a.com"));
+        assert!(!netrc.remove_host("c.com"));
+        assert_eq!(netrc.hosts.len(), 1);
+        assert_eq!(netrc.hosts[0].0, "b.com");
+        assert!(netrc.remove_macro("init"));
+        assert!(!netrc.remove_macro("init"));
+        assert_eq!(netrc.to_string(), "machine b.com login b\ndefault login def\n");
     }
 
-    fn next(&mut self) -> Option<String> {
-        let mut cur = self.cur;
-        for _ in self.remaining().chars().take_while(|c| c.is_whitespace()) {
-            cur += 1;
-        }
-        self.cur = cur;
-        if cur < self.buf.len() {
-            let mut s = String::new();
-            for c in self.remaining().chars().take_while(|c| !c.is_whitespace()) {
-                cur += 1;
-                s.push(c);
+    #[test]
+    fn parse_error_truncated_macdef() {
+        let input = "machine a.com login a
+                     macdef
+
+";
+        match Netrc::parse(input.as_bytes()).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Missing name for `macdef'");
+                assert_eq!(line, 2);
             }
-            self.cur = cur;
-            Some(s)
-        } else {
-            None
+            e => panic!("Wrong Error type: {:?}", e),
         }
     }
-}
 
-struct Lexer<A> {
-    buf: A,
-    line: Tokens,
-    lnum: usize,
-}
+    #[test]
+    fn parse_assignments() {
+        let input = "machine=example.com login=foo password=p@ss=word
+                     port 21 account= acct";
+        let options = ParseOptions { assignments: true, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        let (ref name, ref mach) = netrc.hosts[0];
+        assert_eq!(name, "example.com");
+        assert_eq!(mach.login, "foo");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "p@ss=word");
+        assert_eq!(mach.port, Some(21));
+        assert_eq!(mach.account.as_ref().unwrap().expose(), "acct");
 
-impl<A: BufRead> Lexer<A> {
-    fn new(buf: A) -> Lexer<A> {
-        Lexer { buf: buf, line: Tokens::empty(), lnum: 0 }
+        let input = "machine example.com login foo password p@ss=word";
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts[0].1.password.as_ref().unwrap().expose(), "p@ss=word");
+        assert!(Netrc::parse("machine=example.com login=foo".as_bytes()).is_err());
     }
 
-    fn read_line(&mut self, buf: &mut String) -> Result<usize> {
-        let r = self.buf.read_line(buf).map_err(Error::Io);
-        for &n in r.iter() {
-            if n > 0 { self.lnum += 1 };
-        }
-        r
+    #[test]
+    fn parse_free_function() {
+        let input = "machine example.com login test password p@ss port 42
+                     macdef init
+                     bin
+
+                     default login def";
+        let netrc = crate::parse(input.as_bytes()).unwrap();
+        let expected = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(format!("{:?}", netrc), format!("{:?}", expected));
+        assert!(crate::parse("machine".as_bytes()).is_err());
     }
 
-    fn refill(&mut self) -> Result<usize> {
-        let mut line = String::new();
-        let n = try!(self.read_line(&mut line));
-        self.line = Tokens::new(line);
-        Ok(n)
+    #[test]
+    fn normalize_hosts() {
+        let input = "machine https://example.com/ login a
+                     machine git+ssh://git.example.com// login b
+                     machine other.com login c";
+        let mut netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert!(netrc.get("example.com").is_none());
+        netrc.normalize_hosts();
+        assert_eq!(netrc.get("example.com").unwrap().login, "a");
+        assert_eq!(netrc.get("git.example.com").unwrap().login, "b");
+        assert_eq!(netrc.get("other.com").unwrap().login, "c");
+
+        let options = ParseOptions { normalize_hosts: true, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts[0].0, "example.com");
+        assert_eq!(netrc.resolve("example.com").unwrap().login, "a");
     }
 
-    fn next_word(&mut self) -> Option<Result<String>> {
-        loop {
-            match self.line.next() {
-                Some(w) => return Some(Ok(w)),
-                None    => match self.refill() {
-                    Ok(0)  => return None,
-                    Ok(_)  => (),
-                    Err(e) => return Some(Err(e)),
-                },
-            }
-        }
+    #[test]
+    fn parse_warnings() {
+        let input = "machine example.com login 1234 password \"p@ss \"
+                     macdef empty
+
+                     macdef init
+                     bin
+
+                     macdef last";
+        let options = ParseOptions { quoted_values: true, ..Default::default() };
+        let (netrc, warnings) =
+            Netrc::parse_with_warnings(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.macros.len(), 3);
+        let warnings: Vec<_> = warnings.iter().map(|w| (w.kind, w.line)).collect();
+        assert_eq!(warnings, [(WarningKind::NumericLogin, 1),
+                              (WarningKind::PasswordWhitespace, 1),
+                              (WarningKind::EmptyMacro, 2),
+                              (WarningKind::EmptyMacro, 7)]);
+
+        let (_, warnings) =
+            Netrc::parse_with_warnings("macdef m\n\n".as_bytes(), &options).unwrap();
+        assert_eq!(warnings[0].to_string(), "Macro with an empty body at line 1");
     }
 
-    fn next_word_or_err(&mut self) -> Result<String> {
-        match self.next_word() {
-            Some(w) => w,
-            None    => Err(Error::Parse("Unexpected end of file".to_string(),
-                                        self.lnum)),
-        }
+    #[test]
+    fn same_host_ignores_case() {
+        assert!(same_host("Example.COM", "example.com"));
+        assert!(!same_host("example.com", "example.org"));
+        assert!(!same_host("example.com", "example.com."));
+
+        let input = "machine Example.com login a
+                     machine EXAMPLE.COM login b";
+        let mut netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.get("example.com").unwrap().login, "a");
+        assert_eq!(netrc.lookup_all("example.COM", false).len(), 2);
+        assert!(netrc.get("example.org").is_none());
+        assert!(netrc.remove_host("example.com"));
+        assert!(netrc.hosts.is_empty());
+
+        let options = ParseOptions {
+            on_duplicate_host: DuplicateHost::Error,
+            ..Default::default()
+        };
+        assert!(Netrc::parse_with_options(input.as_bytes(), &options).is_err());
+    }
+
+    #[test]
+    fn stats() {
+        let input = "machine a.com login a
+                     machine b.com login b
+
+                     macdef init
+                     bin
+
+                     default login def
+";
+        let stats = Netrc::parse(input.as_bytes()).unwrap().stats();
+        assert_eq!(stats, NetrcStats {
+            hosts: 2,
+            has_default: true,
+            macros: 1,
+            total_lines: Some(7),
+        });
+        assert_eq!(Netrc::parse_borrowed(input).unwrap().into_owned().stats(), stats);
+        assert_eq!(Netrc::default().stats().total_lines, None);
+
+        let input = "machine a.com\n---\nmachine b.com\nlogin b\n";
+        let sections = Netrc::parse_concatenated_stream(input.as_bytes(), "---").unwrap();
+        assert_eq!(sections[0].lines, Some(1));
+        assert_eq!(sections[1].lines, Some(2));
+    }
+
+    #[test]
+    fn parse_trailing_spaces() {
+        let input = "machine example.com login foo   \npassword bar \t\r\n";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.hosts[0].1.login, "foo");
+        assert_eq!(netrc.hosts[0].1.password.as_ref().unwrap().expose(), "bar");
+
+        let input = "macdef init  \ncd  /pub  \n   \nmachine example.com login foo\n";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.macros[0].body, "  \ncd  /pub  \n\n");
+        assert_eq!(netrc.macros[0].body_trimmed(), "cd  /pub  ");
+        assert_eq!(netrc.macros[0].lines, Some((1, 3)));
+        assert_eq!(netrc.hosts[0].1.login, "foo");
+
+        let input = "\u{feff}macdef init\r\ncd /pub\r\nbin\r\n\r\nmachine a.com login foo\r\n";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.macros[0].body, "\ncd /pub\nbin\n\n");
+        assert_eq!(netrc.macros[0].lines, Some((1, 4)));
+        assert_eq!(netrc.hosts[0].1.login, "foo");
+        let borrowed = Netrc::parse_borrowed(input).unwrap();
+        assert_eq!(borrowed.macros[0].body, netrc.macros[0].body);
+
+        let input = "macdef init\r\nbin\r\n";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.macros[0].body, "\nbin\n\n");
+    }
+
+    #[test]
+    fn is_empty_and_has_credentials_for() {
+        assert!(Netrc::parse("\n  \n".as_bytes()).unwrap().is_empty());
+        let input = "machine bare.com port 21
+                     machine pass.com password p@ss
+                     machine user.com login test";
+        let mut netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert!(!netrc.is_empty());
+        assert!(!netrc.has_credentials_for("bare.com"));
+        assert!(netrc.has_credentials_for("pass.com"));
+        assert!(netrc.has_credentials_for("user.com"));
+        assert!(!netrc.has_credentials_for("other.com"));
+        netrc.default = Some(Machine { login: "def".to_string(), ..Default::default() });
+        assert!(netrc.has_credentials_for("other.com"));
+        assert!(!netrc.has_credentials_for("bare.com"));
+    }
+
+    #[test]
+    fn parse_duplicate_host_merge() {
+        let input = "machine x.com login a port 21
+                     machine y.com login y
+                     machine x.com password b port 22";
+        let options = ParseOptions {
+            on_duplicate_host: DuplicateHost::Merge,
+            ..Default::default()
+        };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts.len(), 2);
+        let (ref name, ref mach) = netrc.hosts[0];
+        assert_eq!(name, "x.com");
+        assert_eq!(mach.login, "a");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "b");
+        assert_eq!(mach.port, Some(22));
+        assert_eq!(mach.line, Some(1));
+        assert_eq!(netrc.hosts[1].0, "y.com");
     }
 
-    fn next_subcommands(&mut self) -> Result<String> {
-        let mut cmds = self.line.remaining().to_string();
-        self.line = Tokens::empty();
-        loop {
-            match self.read_line(&mut cmds) {
-                Ok(0...1) => return Ok(cmds),
-                Ok(_)     => (),
-                Err(e)    => return Err(e),
+    #[test]
+    fn parse_strict_keyword_value() {
+        let input = "machine a.com\n  login password foo";
+        let options = ParseOptions { strict: true, ..Default::default() };
+        match Netrc::parse_with_options(input.as_bytes(), &options).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Missing value for `login' before `password'");
+                assert_eq!(line, 2);
             }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        match Netrc::parse(input.as_bytes()).unwrap_err() {
+            Error::UnknownEntry { keyword, .. } => assert_eq!(keyword, "foo"),
+            e => panic!("Wrong Error type: {:?}", e),
         }
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-    use std::io::BufReader;
 
     #[test]
-    fn parse_simple() {
-        let input = "machine example.com
-                             login test
-                             password p@ssw0rd
-                             port 42";
-        let input = BufReader::new(input.as_bytes());
-        let netrc = Netrc::parse(input).unwrap();
-        assert_eq!(netrc.hosts.len(), 1);
-        assert!(netrc.macros.is_empty());
+    fn redacted() {
+        let input = "machine a.com login a password p account x port 21
+                     default login d password q";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap().redacted();
         let (ref name, ref mach) = netrc.hosts[0];
-        assert_eq!(name, "example.com");
-        assert_eq!(mach.login, "test");
-        assert_eq!(mach.password.as_ref().unwrap(), "p@ssw0rd");
-        assert_eq!(mach.port, Some(42));
-        assert_eq!(mach.account, None);
+        assert_eq!(name, "a.com");
+        assert_eq!(mach.login, "a");
+        assert_eq!(mach.port, Some(21));
+        assert!(mach.password.is_none());
+        assert!(mach.account.is_none());
+        let def = netrc.default.unwrap();
+        assert_eq!(def.login, "d");
+        assert!(def.password.is_none());
     }
 
     #[test]
-    fn parse_macdef() {
-        let input = "machine host1.com login login1
-                     macdef uploadtest
-                            cd /pub/tests
-                            bin
-                            put filename.tar.gz
-                            quit
+    fn find_machine() {
+        let input = "machine a.com login a
+                     default login d";
+        let mut netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.find_machine("A.com").unwrap().login, "a");
+        assert_eq!(netrc.find_machine("b.com").unwrap().login, "d");
+        netrc.find_machine_mut("b.com").unwrap().login = "e".to_string();
+        assert_eq!(netrc.default.as_ref().unwrap().login, "e");
+        netrc.find_machine_mut("a.com").unwrap().port = Some(21);
+        assert_eq!(netrc.hosts[0].1.port, Some(21));
+        netrc.default = None;
+        assert!(netrc.find_machine("b.com").is_none());
+        assert!(netrc.find_machine_mut("b.com").is_none());
+    }
 
-                     machine host2.com login login2";
-        let input = BufReader::new(input.as_bytes());
-        let netrc = Netrc::parse(input).unwrap();
-        assert_eq!(netrc.hosts.len(), 2);
-        for host in netrc.hosts.iter().enumerate() {
-            let (i, &(ref name, ref mach)) = host;
-            let i = i + 1;
-            assert_eq!(name, &format!("host{}.com", i));
-            assert_eq!(mach.login, format!("login{}", i));
+    #[test]
+    fn display_round_trip_default_options() {
+        let x = Machine::builder().login("a b").password("\"abc").account("").build();
+        let y = Machine::builder().login("default").password("p\\w\nx").port(21)
+            .protocol("ftp").build();
+        let netrc = Netrc::builder()
+            .machine("x.com", x)
+            .machine("y.com", y)
+            .default_machine(Machine::builder().login("anonymous").build())
+            .macro_def("init", "cd /pub\nbin")
+            .build();
+        let mut again = Netrc::parse(netrc.to_string().as_bytes()).unwrap();
+        assert_eq!(again.lines, Some(7));
+        again.lines = None;
+        for mach in again.hosts.iter_mut().map(|(_, mach)| mach).chain(&mut again.default) {
+            mach.line = None;
         }
-        assert_eq!(netrc.macros.len(), 1);
-        let (ref name, ref cmds) = netrc.macros[0];
-        assert_eq!(name, "uploadtest");
-        assert_eq!(cmds.trim(), "cd /pub/tests
-                            bin
-                            put filename.tar.gz
-                            quit");
+        for mac in &mut again.macros {
+            mac.lines = None;
+        }
+        assert_eq!(again, netrc);
+
+        let input = "machine a.com login \"a b\" password p\nmachine b.com login b\n";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.to_string(), input);
+        assert_eq!(Netrc::parse(netrc.to_string().as_bytes()).unwrap(), netrc);
     }
 
     #[test]
-    fn parse_default() {
-        let input = "machine example.com login test
+    fn display_round_trip() {
+        let input = "machine a.com login a password p account x port 21 protocol ftp
+                     machine b.com port https
+                     default login anonymous password me@example.com
+                     macdef init
+                     cd /pub
+                     bin
+
+                     macdef end
+                     quit";
+        let options = ParseOptions { port_names: true, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        let output = netrc.to_string();
+        let again = Netrc::parse_with_options(output.as_bytes(), &options).unwrap();
+        assert_eq!(again.to_string(), output);
+        assert_eq!(again.hosts.len(), 2);
+        let (ref name, ref mach) = again.hosts[0];
+        assert_eq!(name, "a.com");
+        assert_eq!(mach.login, "a");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "p");
+        assert_eq!(mach.account.as_ref().unwrap().expose(), "x");
+        assert_eq!(mach.port, Some(21));
+        assert_eq!(mach.protocol.as_deref(), Some("ftp"));
+        assert_eq!(again.hosts[1].1.port_name.as_deref(), Some("https"));
+        assert_eq!(again.default.unwrap().login, "anonymous");
+        assert_eq!(again.macros.len(), 2);
+        for (mac, orig) in again.macros.iter().zip(&netrc.macros) {
+            assert_eq!(mac.name, orig.name);
+            assert_eq!(mac.body, orig.body);
+        }
+    }
+
+    #[test]
+    fn error_is_send_sync() {
+        type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+        fn boxed<E: std::error::Error + Send + Sync + 'static>(e: E) -> BoxError {
+            Box::new(e)
+        }
+        let err = Netrc::parse("machine".as_bytes()).unwrap_err();
+        assert_eq!(boxed(err).to_string(), "Missing value for `machine' at line 1");
+    }
+
+    #[test]
+    fn parse_quoted_escapes() {
+        let input = concat!(r#"machine example.com login "a \"b\"" password "p\\w\nx""#,
+                            " account \"\"");
+        let options = ParseOptions { quoted_values: true, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        let mach = &netrc.hosts[0].1;
+        assert_eq!(mach.login, "a \"b\"");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "p\\w\nx");
+        assert_eq!(mach.account.as_ref().unwrap().expose(), "");
+
+        let output = netrc.to_string();
+        assert_eq!(output, concat!(r#"machine example.com login "a \"b\"" password "p\\w\nx""#,
+                                   " account \"\"\n"));
+        let again = Netrc::parse_with_options(output.as_bytes(), &options).unwrap();
+        assert_eq!(again.to_string(), output);
+        assert_eq!(again.hosts[0].1.password, mach.password);
+        let again = Netrc::parse(output.as_bytes()).unwrap();
+        assert_eq!(again.hosts[0].1.login, mach.login);
+
+        let options = ParseOptions { quoted_values: false, ..Default::default() };
+        let input = r#"machine a.com password "p\q""#;
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts[0].1.password.as_ref().unwrap().expose(), r#""p\q""#);
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert_eq!(netrc.hosts[0].1.password.as_ref().unwrap().expose(), "pq");
+    }
+
+    #[test]
+    fn parse_comments() {
+        let input = "# credentials
+                     machine a.com login a # the admin
+                       password #secret
+                     #machine b.com login b
+                     macdef init
+                     # kept
+                     bin
+
                      default login def";
-        let input = BufReader::new(input.as_bytes());
-        let netrc = Netrc::parse(input).unwrap();
+        let options = ParseOptions { comments: true, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
         assert_eq!(netrc.hosts.len(), 1);
-        let (ref name, ref mach) = netrc.hosts[0];
-        assert_eq!(name, "example.com");
-        assert_eq!(mach.login, "test");
-        let def_mach = netrc.default.unwrap();
-        assert_eq!(def_mach.login, "def");
+        let mach = &netrc.hosts[0].1;
+        assert_eq!(mach.login, "a");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "#secret");
+        assert!(netrc.macros[0].body.contains("# kept\n"));
+        assert_eq!(netrc.default.unwrap().login, "def");
+        match Netrc::parse(input.as_bytes()).unwrap_err() {
+            Error::UnknownEntry { keyword, line } => {
+                assert_eq!(keyword, "#");
+                assert_eq!(line, 1);
+            }
+            e => panic!("Wrong Error type: {:?}", e),
+        }
     }
 
     #[test]
-    fn parse_error_unknown_entry() {
-        let input = "machine foobar.com
-                             foo";
-        let input = BufReader::new(input.as_bytes());
-        match Netrc::parse(input).unwrap_err() {
-            Error::Parse(msg, lnum) => {
-                assert_eq!(msg, "Unknown entry `foo'");
-                assert_eq!(lnum, 2);
-            }
+    fn parse_unknown_entries() {
+        let input = "machine a.com login a
+                     bogus words
+                     machine b.com login b";
+        let parse = |policy| {
+            let options = ParseOptions { unknown_entries: policy, ..Default::default() };
+            Netrc::parse_with_warnings(input.as_bytes(), &options)
+        };
+        assert!(matches!(parse(UnknownEntryPolicy::Error),
+                         Err(Error::UnknownEntry { line: 2, .. })));
+        let (netrc, warnings) = parse(UnknownEntryPolicy::Skip).unwrap();
+        assert_eq!(netrc.hosts.len(), 2);
+        assert!(warnings.is_empty());
+        let (netrc, warnings) = parse(UnknownEntryPolicy::Warn).unwrap();
+        assert_eq!(netrc.hosts[1].1.login, "b");
+        assert_eq!(warnings, [Warning { kind: WarningKind::UnknownEntry, line: 2 },
+                              Warning { kind: WarningKind::UnknownEntry, line: 2 }]);
+    }
+
+    #[test]
+    fn parse_case_insensitive_keywords() {
+        let input = "Machine a.com LOGIN A Password p\nDEFAULT login d";
+        let options = ParseOptions { case_insensitive_keywords: true, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        let mach = &netrc.hosts[0].1;
+        assert_eq!(mach.login, "A");
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "p");
+        assert_eq!(netrc.default.unwrap().login, "d");
+        assert!(Netrc::parse(input.as_bytes()).is_err());
+
+        let options = ParseOptions { strict: true, ..options };
+        match Netrc::parse_with_options("machine a.com login PASSWORD p".as_bytes(), &options)
+            .unwrap_err() {
+            Error::Parse { msg, .. } =>
+                assert_eq!(msg, "Missing value for `login' before `PASSWORD'"),
             e => panic!("Wrong Error type: {:?}", e),
         }
     }
 
     #[test]
-    fn parse_error_unexpected_eof() {
-        let input = "machine foobar.com
-                             password quux
-                             login";
-        let input = BufReader::new(input.as_bytes());
-        match Netrc::parse(input).unwrap_err() {
-            Error::Parse(msg, lnum) => {
-                assert_eq!(msg, "Unexpected end of file");
-                assert_eq!(lnum, 3);
+    fn parse_recovering() {
+        let input = "machine a.com login a port x password p
+                     machine b.com login b
+                     bogus
+                     machine c.com login c
+                     default login d password";
+        let (netrc, errors) = Netrc::parse_recovering(input.as_bytes(), &Default::default())
+            .unwrap();
+        let logins: Vec<_> = netrc.hosts.iter().map(|(_, m)| m.login.as_str()).collect();
+        assert_eq!(logins, ["a", "b", "c"]);
+        assert!(netrc.hosts[0].1.password.is_none());
+        assert_eq!(netrc.default.unwrap().login, "d");
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(errors, ["Unable to parse port number `x' at line 1",
+                            "Unknown entry `bogus' at line 3",
+                            "Missing value for `password' at line 5"]);
+
+        let options = ParseOptions { max_line_length: 30, ..Default::default() };
+        let input = format!("machine a.com login {}\nmachine b.com login b\n", "a".repeat(40));
+        let (netrc, errors) = Netrc::parse_recovering(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts.len(), 1);
+        assert_eq!(netrc.hosts[0].0, "b.com");
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], Error::Parse { line: 1, .. }));
+        assert_eq!(netrc.lines, Some(2));
+    }
+
+    #[test]
+    fn machine_for_url() {
+        let input = "machine example.com login any
+                     machine example.com login alt port 8080
+                     machine ::1 login v6 port 99
+                     default login def";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let login = |url: &str| netrc.machine_for_url(url).map(|m| m.login.as_str());
+        assert_eq!(login("https://example.com"), Some("any"));
+        assert_eq!(login("http://user:pw@EXAMPLE.com:8080/a?b#c"), Some("alt"));
+        assert_eq!(login("ftp://other.com/"), Some("def"));
+        assert_eq!(login("http://[::1]:99/"), Some("v6"));
+        assert_eq!(login("example.com"), Some("def"));
+        assert_eq!(login("http://example.com:port/"), Some("def"));
+        assert_eq!(login("http://[::1/"), Some("def"));
+        assert_eq!(url_host_port("http://example.com:port/"), None);
+
+        assert_eq!(url_host_port("http://[::1]:99/"), Some(("::1", Some(99))));
+        assert_eq!(url_host_port("git+ssh://git@host.com/repo"), Some(("host.com", None)));
+        assert_eq!(url_host_port("https://host.com:/"), Some(("host.com", Some(443))));
+    }
+
+    #[test]
+    fn rename_and_dedup_hosts() {
+        let input = "machine old.com login a
+                     machine new.com login b
+                     machine OLD.com login c";
+        let mut netrc = Netrc::parse(input.as_bytes()).unwrap();
+        assert!(netrc.rename_host("old.com", "new.com"));
+        assert!(!netrc.rename_host("old.com", "new.com"));
+        let names: Vec<_> = netrc.hosts.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["new.com", "new.com", "new.com"]);
+        assert_eq!(netrc.get("new.com").unwrap().login, "a");
+
+        netrc.upsert_machine("x.com", Machine { login: "x".to_string(), ..Default::default() });
+        netrc.dedup_hosts();
+        let logins: Vec<_> = netrc.hosts.iter().map(|(_, m)| m.login.as_str()).collect();
+        assert_eq!(logins, ["a", "x"]);
+    }
+
+    #[test]
+    fn parse_resolve_port_names() {
+        let input = "machine a.com port imaps
+                     machine b.com port SMTP
+                     machine c.com port imap-s2";
+        let options = ParseOptions {
+            port_names: true,
+            resolve_port_names: true,
+            ..Default::default()
+        };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts[0].1.port, Some(993));
+        assert_eq!(netrc.hosts[0].1.port_name, None);
+        assert_eq!(netrc.hosts[1].1.port, Some(25));
+        assert_eq!(netrc.hosts[2].1.port, None);
+        assert_eq!(netrc.hosts[2].1.port_name.as_deref(), Some("imap-s2"));
+        assert_eq!(netrc.get_host_port("a.com", 993).unwrap().port, Some(993));
+    }
+
+    #[test]
+    fn parse_host_ports() {
+        let input = "machine example.com:8080 login a
+                     machine [::1]:99 login b
+                     machine ::1 login c
+                     machine [::2] login d
+                     machine bad.com:http login e";
+        let options = ParseOptions { host_ports: true, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        let hosts: Vec<_> = netrc.hosts.iter()
+            .map(|(name, mach)| (name.as_str(), mach.port))
+            .collect();
+        assert_eq!(hosts, [("example.com", Some(8080)), ("::1", Some(99)), ("::1", None),
+                           ("[::2]", None), ("bad.com:http", None)]);
+        assert_eq!(netrc.get_host_port("example.com", 8080).unwrap().login, "a");
+        assert_eq!(netrc.machine_for_url("http://[::1]:99/").unwrap().login, "b");
+
+        let options = ParseOptions { host_ports: true, strict: true, ..Default::default() };
+        let input = "machine example.com:8080\nmachine b.com:21 login b port 22";
+        match Netrc::parse_with_options(input.as_bytes(), &options).unwrap_err() {
+            Error::Parse { msg, line } => {
+                assert_eq!(msg, "Duplicate `port' in the same entry");
+                assert_eq!(line, 2);
             }
             e => panic!("Wrong Error type: {:?}", e),
         }
     }
 
     #[test]
-    fn parse_error_no_machine() {
-        let input = "password quux login foo";
-        let input = BufReader::new(input.as_bytes());
-        match Netrc::parse(input).unwrap_err() {
-            Error::Parse(msg, lnum) => {
-                assert_eq!(msg, "No machine defined for password");
-                assert_eq!(lnum, 1);
-            }
+    fn parse_authinfo() {
+        let input = "# Gnus
+                     machine imap.example.com login foo port imaps password \"p w\" force yes
+                     machine smtp.example.com port 587 login bar
+                     default login anon x-note \"none given\"";
+        let options = ParseOptions::authinfo();
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        let mach = &netrc.hosts[0].1;
+        assert_eq!(mach.port_name.as_deref(), Some("imaps"));
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "p w");
+        assert_eq!(mach.extras, [("force".to_string(), "yes".to_string())]);
+        assert_eq!(netrc.hosts[1].1.port, Some(587));
+        assert_eq!(netrc.default.as_ref().unwrap().extras,
+                   [("x-note".to_string(), "none given".to_string())]);
+        let written = netrc.to_string();
+        assert_eq!(written,
+                   "machine imap.example.com login foo password \"p w\" port imaps force yes\n\
+                    machine smtp.example.com login bar port 587\n\
+                    default login anon x-note \"none given\"\n");
+        let reparsed = Netrc::parse_with_options(written.as_bytes(), &options).unwrap();
+        assert_eq!(reparsed.to_string(), written);
+
+        assert!(Netrc::parse(input.as_bytes()).is_err());
+        match Netrc::parse_with_options("force yes".as_bytes(), &options).unwrap_err() {
+            Error::UnknownEntry { keyword, .. } => assert_eq!(keyword, "force"),
+            e => panic!("Wrong Error type: {:?}", e),
+        }
+        match Netrc::parse_with_options("default force".as_bytes(), &options).unwrap_err() {
+            Error::Parse { msg, .. } => assert_eq!(msg, "Missing value for `force'"),
             e => panic!("Wrong Error type: {:?}", e),
         }
     }
 
     #[test]
-    fn parse_error_port() {
-        let input = "machine foo.com login bar port quux";
-        let input = BufReader::new(input.as_bytes());
+    fn parse_encodings() {
+        let input: &[u8] = b"machine a.com login Andr\xe9\nmacdef init\ncd /caf\xe9\n\n";
         match Netrc::parse(input).unwrap_err() {
-            Error::Parse(msg, lnum) => {
-                assert_eq!(msg, "Unable to parse port number `quux'");
-                assert_eq!(lnum, 1);
-            }
+            Error::Io(e) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
             e => panic!("Wrong Error type: {:?}", e),
         }
+        let netrc = Netrc::parse_lossy(input).unwrap();
+        assert_eq!(netrc.hosts[0].1.login, "Andr\u{fffd}");
+        assert_eq!(netrc.macros[0].body_trimmed(), "cd /caf\u{fffd}");
+
+        let options = ParseOptions { encoding: Encoding::Latin1, ..Default::default() };
+        let netrc = Netrc::parse_with_options(input, &options).unwrap();
+        assert_eq!(netrc.hosts[0].1.login, "Andr\u{e9}");
+        assert_eq!(netrc.macros[0].body_trimmed(), "cd /caf\u{e9}");
+        let input = "machine a.com login \u{e9}";
+        let netrc = Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts[0].1.login, "\u{c3}\u{a9}");
     }
 }
@@ -0,0 +1,312 @@
+//! Checks for likely mistakes in a netrc that still parses, each reported
+//! by a rule whose severity callers may change, or turn off with
+//! `Severity::Allow`.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{same_host, Machine, Netrc};
+
+/// What a `Diagnostic` is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Rule {
+    /// A `machine` entry for a host already named by an earlier entry,
+    /// with another port.  Only port-aware lookups reach it.
+    DuplicateHost,
+    /// A `machine` entry for the same host and port as an earlier one,
+    /// which no lookup ever reaches.
+    ShadowedEntry,
+    /// A `machine` entry after `default`, which BSD `ftp` ignores.
+    EntryAfterDefault,
+    /// An entry with a login but no password.
+    MissingPassword,
+    /// A password shorter than `LintOptions.min_password_length`.
+    ShortPassword,
+    /// Passwords in a file that other users may read.  Only checked by
+    /// `lint_file`, on Unix.
+    WorldReadable,
+}
+
+impl Rule {
+    /// Every rule, in declaration order.
+    pub const ALL: [Rule; 6] = [Rule::DuplicateHost, Rule::ShadowedEntry,
+                                Rule::EntryAfterDefault, Rule::MissingPassword,
+                                Rule::ShortPassword, Rule::WorldReadable];
+
+    /// A stable name for the rule, such as `shadowed-entry`, for
+    /// configuration files and command line flags.
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::DuplicateHost     => "duplicate-host",
+            Rule::ShadowedEntry     => "shadowed-entry",
+            Rule::EntryAfterDefault => "entry-after-default",
+            Rule::MissingPassword   => "missing-password",
+            Rule::ShortPassword     => "short-password",
+            Rule::WorldReadable     => "world-readable",
+        }
+    }
+
+    /// The rule named `id`, as returned by `Rule::id`.
+    pub fn from_id(id: &str) -> Option<Rule> {
+        Rule::ALL.iter().copied().find(|rule| rule.id() == id)
+    }
+
+    /// The severity of the rule unless `LintOptions.severities` says
+    /// otherwise.
+    pub fn default_severity(self) -> Severity {
+        match self {
+            Rule::ShadowedEntry | Rule::WorldReadable => Severity::Error,
+            Rule::MissingPassword                     => Severity::Allow,
+            _                                         => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+/// How much a rule matters.  Rules at `Allow` are not checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Allow,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Allow   => "allow",
+            Severity::Warning => "warning",
+            Severity::Error   => "error",
+        })
+    }
+}
+
+/// Options for `lint`.
+#[derive(Clone, Debug)]
+pub struct LintOptions {
+    /// Severities replacing those of `Rule::default_severity`; a later pair
+    /// for the same rule wins.
+    pub severities: Vec<(Rule, Severity)>,
+    /// Passwords shorter than this many characters break
+    /// `Rule::ShortPassword`.  8 by default.
+    pub min_password_length: usize,
+}
+
+impl Default for LintOptions {
+    fn default() -> LintOptions {
+        LintOptions { severities: Vec::new(), min_password_length: 8 }
+    }
+}
+
+impl LintOptions {
+    /// The severity of `rule` with these options.
+    pub fn severity(&self, rule: Rule) -> Severity {
+        self.severities.iter().rev()
+            .find(|&&(r, _)| r == rule)
+            .map_or(rule.default_severity(), |&(_, severity)| severity)
+    }
+}
+
+/// A rule broken by a netrc.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    /// The line of the entry at fault.  `None` if the netrc was not parsed
+    /// or the whole file is at fault.
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.rule, self.message)?;
+        if let Some(line) = self.line {
+            write!(f, " at line {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+struct Linter<'o> {
+    options: &'o LintOptions,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'o> Linter<'o> {
+    fn report(&mut self, rule: Rule, line: Option<usize>, message: String) {
+        let severity = self.options.severity(rule);
+        if severity != Severity::Allow {
+            self.diagnostics.push(Diagnostic { rule, severity, message, line });
+        }
+    }
+
+    fn machine(&mut self, entry: &str, mach: &Machine) {
+        let password = match mach.password {
+            Some(ref password) => password.expose(),
+            None               => {
+                if !mach.login.is_empty() {
+                    self.report(Rule::MissingPassword, mach.line,
+                                format!("No password for {}", entry));
+                }
+                return;
+            }
+        };
+        let len = password.chars().count();
+        if len < self.options.min_password_length {
+            let msg = format!("Password of {} is only {} characters long", entry, len);
+            self.report(Rule::ShortPassword, mach.line, msg);
+        }
+    }
+}
+
+/// Check `netrc` against every rule not at `Severity::Allow`, returning
+/// what is wrong in input order.  Rules that need the file itself, such as
+/// `Rule::WorldReadable`, are left to `lint_file`.
+///
+/// # Examples
+///
+/// ```
+/// use netrc::lint::{self, LintOptions, Rule, Severity};
+/// use netrc::Netrc;
+///
+/// let input = "machine a.com login a password hunter2
+///              machine a.com login b password correct-horse";
+/// let netrc = Netrc::parse_str(input, &Default::default()).unwrap();
+/// let diagnostics = lint::lint(&netrc, &LintOptions::default());
+/// let rules: Vec<Rule> = diagnostics.iter().map(|d| d.rule).collect();
+/// assert_eq!(rules, [Rule::ShortPassword, Rule::ShadowedEntry]);
+///
+/// let options = LintOptions { severities: vec![(Rule::ShortPassword, Severity::Allow)],
+///                             ..Default::default() };
+/// assert_eq!(lint::lint(&netrc, &options).len(), 1);
+/// ```
+pub fn lint(netrc: &Netrc, options: &LintOptions) -> Vec<Diagnostic> {
+    let mut linter = Linter { options, diagnostics: Vec::new() };
+    let default_line = netrc.default.as_ref().and_then(|mach| mach.line);
+    for (i, (host, mach)) in netrc.hosts.iter().enumerate() {
+        let earlier: Vec<&Machine> = netrc.hosts[..i].iter()
+            .filter(|(name, _)| same_host(name, host))
+            .map(|(_, earlier)| earlier)
+            .collect();
+        let shadowing = earlier.iter()
+            .find(|earlier| earlier.port == mach.port && earlier.port_name == mach.port_name);
+        if let Some(shadowing) = shadowing {
+            let msg = match shadowing.line {
+                Some(line) => format!("Entry for `{}' shadowed by the one at line {}", host, line),
+                None       => format!("Entry for `{}' shadowed by an earlier one", host),
+            };
+            linter.report(Rule::ShadowedEntry, mach.line, msg);
+        } else if !earlier.is_empty() {
+            linter.report(Rule::DuplicateHost, mach.line,
+                          format!("Host `{}' has several entries", host));
+        }
+        if let (Some(line), Some(default_line)) = (mach.line, default_line) {
+            if line > default_line {
+                linter.report(Rule::EntryAfterDefault, mach.line,
+                              format!("Entry for `{}' after `default'", host));
+            }
+        }
+        linter.machine(&format!("`{}'", host), mach);
+    }
+    if let Some(ref mach) = netrc.default {
+        linter.machine("`default'", mach);
+    }
+    linter.diagnostics.sort_by_key(|d| d.line);
+    linter.diagnostics
+}
+
+/// Parse the netrc file at `path` and `lint` it, also checking that on
+/// Unix no other user may read it if it has passwords.
+///
+/// Only available with the `fs` feature, which is on by default.
+#[cfg(feature = "fs")]
+pub fn lint_file<P: AsRef<std::path::Path>>(path: P,
+                                            options: &LintOptions) -> crate::Result<Vec<Diagnostic>> {
+    let path = path.as_ref();
+    let netrc = Netrc::from_file(path)?;
+    let mut linter = Linter { options, diagnostics: Vec::new() };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let has_password = netrc.hosts.iter().map(|(_, mach)| mach)
+            .chain(&netrc.default)
+            .any(|mach| mach.password.is_some());
+        let mode = std::fs::metadata(path)?.mode();
+        if has_password && mode & 0o004 != 0 {
+            linter.report(Rule::WorldReadable, None,
+                          format!("Passwords readable by any user (mode {:o})", mode & 0o777));
+        }
+    }
+    linter.diagnostics.extend(lint(&netrc, options));
+    Ok(linter.diagnostics)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::{lint, LintOptions, Rule, Severity};
+    use crate::Netrc;
+
+    #[test]
+    fn lint_rules() {
+        let input = "machine a.com login a password long-enough
+                     machine b.com login b
+                     default login anonymous password guest1234
+                     machine A.COM login c password also-long port 8080
+                     machine a.com login d password short";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let options = LintOptions {
+            severities: vec![(Rule::MissingPassword, Severity::Warning)],
+            ..Default::default()
+        };
+        let found: Vec<_> = lint(&netrc, &options).iter()
+            .map(|d| (d.rule, d.severity, d.line))
+            .collect();
+        assert_eq!(found, [
+            (Rule::MissingPassword, Severity::Warning, Some(2)),
+            (Rule::DuplicateHost, Severity::Warning, Some(4)),
+            (Rule::EntryAfterDefault, Severity::Warning, Some(4)),
+            (Rule::ShadowedEntry, Severity::Error, Some(5)),
+            (Rule::EntryAfterDefault, Severity::Warning, Some(5)),
+            (Rule::ShortPassword, Severity::Warning, Some(5)),
+        ]);
+        let diagnostic = &lint(&netrc, &options)[3];
+        assert_eq!(diagnostic.to_string(),
+                   "error[shadowed-entry]: Entry for `a.com' shadowed by the one at line 1 at line 5");
+    }
+
+    #[test]
+    fn rule_ids() {
+        for rule in Rule::ALL {
+            assert_eq!(Rule::from_id(rule.id()), Some(rule));
+        }
+        assert_eq!(Rule::from_id("nope"), None);
+    }
+
+    #[cfg(all(unix, feature = "fs"))]
+    #[test]
+    fn lint_world_readable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("netrc-lint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("netrc");
+        std::fs::write(&path, "machine a.com login a password long-enough\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let found = super::lint_file(&path, &Default::default()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].rule, Rule::WorldReadable);
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(super::lint_file(&path, &Default::default()).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,905 @@
+//! The line-driven parsing state machine shared by every front end.
+
+use alloc::borrow::Cow;
+use alloc::collections::btree_map::Entry;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::{DuplicateHost, Error, Machine, Macro, Netrc, ParseOptions, Result,
+            UnknownEntryPolicy, Warning, WarningKind};
+#[cfg(all(unix, feature = "fs"))]
+use crate::file::system_service_port;
+
+/// An entry recognized by the `Parser`, borrowing from the current line
+/// unless a quoted word had escapes.
+pub(crate) enum Event<'l> {
+    Machine(Cow<'l, str>),
+    Default,
+    Login(Cow<'l, str>),
+    Password(Cow<'l, str>),
+    Account(Cow<'l, str>),
+    Port(u16),
+    PortName(Cow<'l, str>),
+    Protocol(Cow<'l, str>),
+    /// A field with another keyword, with `ParseOptions.extra_fields`.
+    Extra(Cow<'l, str>, Cow<'l, str>),
+    MacroDef(Cow<'l, str>),
+    /// A piece of the current macro body.  Pieces are contiguous in the
+    /// input, except for the line breaks ending a macro at end of input.
+    MacroText(&'l str),
+    MacroEnd,
+}
+
+/// Receives the events of a `Parser` along with their line numbers.
+pub(crate) trait Sink<'l> {
+    fn event(&mut self, event: Event<'l>, line: usize);
+
+    /// Receives each word read as a keyword or value, before its event.
+    fn word(&mut self, _word: &str, _line: usize) {}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Keyword {
+    Machine,
+    Login,
+    Password,
+    Account,
+    Port,
+    Protocol,
+}
+
+impl Keyword {
+    fn name(self) -> &'static str {
+        match self {
+            Keyword::Machine  => "machine",
+            Keyword::Login    => "login",
+            Keyword::Password => "password",
+            Keyword::Account  => "account",
+            Keyword::Port     => "port",
+            Keyword::Protocol => "protocol",
+        }
+    }
+}
+
+/// A `machine` or `default` entry, tracked for `ParseOptions.strict`.
+struct Block {
+    /// The host name, or `None` for `default`.
+    host: Option<String>,
+    line: usize,
+    /// The fields given so far, in order.
+    fields: Vec<Keyword>,
+}
+
+enum State {
+    /// Expecting an entry keyword.
+    Entry,
+    /// Expecting the value of a keyword.
+    Value(Keyword),
+    /// Expecting the value of a field with another keyword.
+    ExtraValue(String),
+    /// Expecting the name following `macdef`.
+    MacroName,
+    /// Collecting the body of a macro.
+    MacroBody,
+}
+
+/// Line-driven parsing state machine.
+///
+/// A front end reads its input one line at a time, line terminator
+/// included, hands each line to `feed_line` and calls `finish` at end of
+/// input.  This lets blocking and async readers share the same parser,
+/// while the `Sink` decides what to build from the recognized entries.
+pub(crate) struct Parser<'o, S> {
+    pub options: Cow<'o, ParseOptions>,
+    pub sink: S,
+    pub lnum: usize,
+    /// The number of lines fed, which differs from `lnum` when counting
+    /// starts further into a stream.
+    pub lines: usize,
+    state: State,
+    /// Whether a `machine` or `default` entry is open to take fields.
+    in_machine: bool,
+    /// Whether the open `machine` entry was rejected by the host filter.
+    skipping: bool,
+    /// Lowercased names of the stored `machine` entries, unless duplicates
+    /// are kept.
+    seen: BTreeSet<String>,
+    /// With `options.strict`, the entry taking fields and the one before.
+    block: Option<Block>,
+    prev_block: Option<Block>,
+    hosts: usize,
+    macros: usize,
+    macro_length: usize,
+    /// The line of the `macdef` keyword of the current macro, where errors
+    /// about the macro are reported.
+    macro_line: usize,
+    /// Whether the macro body read so far ends with a line break.
+    macro_newline: bool,
+    /// Whether the macro body read so far is only whitespace.
+    macro_blank: bool,
+    pub warnings: Vec<Warning>,
+    /// If set, errors are collected here instead of ending the parse, which
+    /// resumes at the next `machine` or `default` keyword.
+    pub errors: Option<Vec<Error>>,
+    /// Whether words are being skipped after an error.
+    resyncing: bool,
+}
+
+impl<'o, S> Parser<'o, S> {
+    pub fn new(options: &'o ParseOptions, sink: S) -> Parser<'o, S> {
+        Parser::with_options(Cow::Borrowed(options), sink)
+    }
+
+    /// A parser keeping its own copy of the options, for a front end that
+    /// cannot borrow them for as long as it parses.
+    #[cfg(feature = "std")]
+    pub fn with_owned_options(options: ParseOptions, sink: S) -> Parser<'static, S> {
+        Parser::with_options(Cow::Owned(options), sink)
+    }
+
+    fn with_options(options: Cow<'o, ParseOptions>, sink: S) -> Parser<'o, S> {
+        Parser {
+            options,
+            sink,
+            lnum: 0,
+            lines: 0,
+            state: State::Entry,
+            in_machine: false,
+            skipping: false,
+            seen: BTreeSet::new(),
+            block: None,
+            prev_block: None,
+            hosts: 0,
+            macros: 0,
+            macro_length: 0,
+            macro_line: 0,
+            macro_newline: false,
+            macro_blank: true,
+            warnings: Vec::new(),
+            errors: None,
+            resyncing: false,
+        }
+    }
+
+    /// Whether the next word must start an entry.
+    #[cfg(feature = "fs")]
+    pub fn expects_entry(&self) -> bool {
+        matches!(self.state, State::Entry)
+    }
+
+    pub fn feed_line<'l>(&mut self, line: &'l str) -> Result<()>
+        where S: Sink<'l>
+    {
+        // Windows editors may start the file with a byte order mark.
+        let line = match line.strip_prefix('\u{feff}') {
+            Some(rest) if self.lnum == 0 => rest,
+            _                            => line,
+        };
+        self.lnum += 1;
+        self.lines += 1;
+        if line.len() > self.options.max_line_length {
+            let msg = format!("Line exceeds the maximum length of {} bytes",
+                              self.options.max_line_length);
+            return self.recover(Err(self.error(msg)));
+        }
+        if let State::MacroBody = self.state {
+            let result = if line.trim().is_empty() {
+                // A blank line ends the macro, even with stray whitespace or
+                // a carriage return, and is stored as a bare line break.
+                self.macro_text(if line == "\n" { line } else { "\n" })
+                    .map(|()| self.end_macro())
+            } else {
+                self.macro_text(line)
+            };
+            return self.recover(result);
+        }
+        let mut tokens = Tokens::new(line, self.options.strict_whitespace);
+        while let Some(word) = tokens.next(self.options.quoted_values) {
+            if self.resyncing {
+                if !self.starts_entry(&word) {
+                    continue;
+                }
+                self.resyncing = false;
+            }
+            if self.options.comments && word.starts_with('#')
+                && matches!(self.state, State::Entry) {
+                break;
+            }
+            let assignment = match word {
+                Cow::Borrowed(w) if self.options.assignments
+                                    && matches!(self.state, State::Entry) => w.split_once('='),
+                _ => None,
+            };
+            let result = match assignment {
+                Some((key, value)) => self.parse_word(key.into()).and_then(|()| {
+                    if value.is_empty() { Ok(()) } else { self.parse_word(value.into()) }
+                }),
+                None => self.parse_word(word),
+            };
+            self.recover(result)?;
+            if let State::MacroBody = self.state {
+                let result = self.macro_text(tokens.remaining());
+                self.recover(result)?;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish<'l>(mut self) -> Result<S>
+        where S: Sink<'l>
+    {
+        let result = self.end();
+        self.recover(result)?;
+        Ok(self.sink)
+    }
+
+    /// Pass on `result`, unless errors are being collected: then record
+    /// the error and skip to the next `machine` or `default` keyword.
+    fn recover(&mut self, result: Result<()>) -> Result<()> {
+        let (errors, e) = match (&mut self.errors, result) {
+            (Some(errors), Err(e)) => (errors, e),
+            (_, result)            => return result,
+        };
+        errors.push(e);
+        self.resyncing = true;
+        self.state = State::Entry;
+        self.in_machine = false;
+        self.skipping = false;
+        self.block = None;
+        self.prev_block = None;
+        Ok(())
+    }
+
+    /// Whether `word` is a keyword starting a `machine` or `default` entry.
+    fn starts_entry(&self, word: &str) -> bool {
+        let keywords = ["machine", "default"];
+        if self.options.case_insensitive_keywords {
+            keywords.iter().any(|k| k.eq_ignore_ascii_case(word))
+        } else {
+            keywords.contains(&word)
+        }
+    }
+
+    /// Whether `word` is a keyword, in any case with
+    /// `case_insensitive_keywords`.
+    fn keyword_like(&self, word: &str) -> bool {
+        if self.options.case_insensitive_keywords {
+            is_keyword(&word.to_ascii_lowercase())
+        } else {
+            is_keyword(word)
+        }
+    }
+
+    /// Handle the end of input.
+    fn end<'l>(&mut self) -> Result<()>
+        where S: Sink<'l>
+    {
+        match self.state {
+            State::Entry     => self.close_block()?,
+            State::MacroBody => {
+                // Store the body as if the missing blank line were there.
+                let term = if self.macro_newline { "\n" } else { "\n\n" };
+                self.macro_text(term)?;
+                self.end_macro();
+            }
+            State::Value(keyword) => {
+                let msg = format!("Missing value for `{}'", keyword.name());
+                return Err(self.error(msg));
+            }
+            State::ExtraValue(ref keyword) => {
+                let msg = format!("Missing value for `{}'", keyword);
+                return Err(self.error(msg));
+            }
+            State::MacroName => {
+                return Err(self.macro_error("Missing name for `macdef'".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    fn warn(&mut self, kind: WarningKind, line: usize) {
+        self.warnings.push(Warning { kind, line });
+    }
+
+    fn error(&self, msg: String) -> Error {
+        Error::Parse { msg, line: self.lnum }
+    }
+
+    fn macro_error(&self, msg: String) -> Error {
+        Error::Parse { msg, line: self.macro_line }
+    }
+
+    fn parse_word<'l>(&mut self, word: Cow<'l, str>) -> Result<()>
+        where S: Sink<'l>
+    {
+        self.sink.word(&word, self.lnum);
+        match core::mem::replace(&mut self.state, State::Entry) {
+            State::Entry          => self.parse_entry(&word),
+            State::Value(keyword) => self.parse_value(keyword, word),
+            State::ExtraValue(keyword) => {
+                if self.options.strict && self.keyword_like(&word) {
+                    let msg = format!("Missing value for `{}' before `{}'", keyword, word);
+                    return Err(self.error(msg));
+                }
+                if !self.skipping {
+                    self.sink.event(Event::Extra(keyword.into(), word), self.lnum);
+                }
+                Ok(())
+            }
+            State::MacroName      => {
+                self.macros += 1;
+                if self.macros > self.options.max_macros {
+                    let msg = format!("Too many macro definitions (limit {})",
+                                      self.options.max_macros);
+                    return Err(self.macro_error(msg));
+                }
+                self.sink.event(Event::MacroDef(word), self.lnum);
+                self.state = State::MacroBody;
+                self.macro_length = 0;
+                self.macro_newline = false;
+                self.macro_blank = true;
+                Ok(())
+            }
+            State::MacroBody      => unreachable!(),
+        }
+    }
+
+    fn parse_entry<'l>(&mut self, item: &str) -> Result<()>
+        where S: Sink<'l>
+    {
+        let lower;
+        let item = if self.options.case_insensitive_keywords {
+            lower = item.to_ascii_lowercase();
+            &lower
+        } else {
+            item
+        };
+        let keyword = match item {
+            "machine"  => Keyword::Machine,
+            "default"  => {
+                self.open_block(None)?;
+                self.sink.event(Event::Default, self.lnum);
+                self.in_machine = true;
+                self.skipping = false;
+                return Ok(());
+            }
+            "login"    => Keyword::Login,
+            "password" => Keyword::Password,
+            "account"  => Keyword::Account,
+            "port"     => Keyword::Port,
+            "protocol" => Keyword::Protocol,
+            "macdef"   => {
+                self.close_block()?;
+                self.prev_block = None;
+                self.in_machine = false;
+                self.skipping = false;
+                self.macro_line = self.lnum;
+                self.state = State::MacroName;
+                return Ok(());
+            }
+            _ if self.options.extra_fields && self.in_machine => {
+                self.state = State::ExtraValue(item.to_string());
+                return Ok(());
+            }
+            _ => {
+                match self.options.unknown_entries {
+                    UnknownEntryPolicy::Error => {
+                        let keyword = item.to_string();
+                        return Err(Error::UnknownEntry { keyword, line: self.lnum });
+                    }
+                    UnknownEntryPolicy::Skip  => (),
+                    UnknownEntryPolicy::Warn  => self.warn(WarningKind::UnknownEntry, self.lnum),
+                }
+                return Ok(());
+            }
+        };
+        if keyword != Keyword::Machine && !self.in_machine {
+            return Err(self.error(format!("No machine defined for {}", item)));
+        }
+        self.state = State::Value(keyword);
+        Ok(())
+    }
+
+    fn parse_value<'l>(&mut self, keyword: Keyword, value: Cow<'l, str>) -> Result<()>
+        where S: Sink<'l>
+    {
+        if self.options.strict && keyword != Keyword::Machine && self.keyword_like(&value) {
+            let msg = format!("Missing value for `{}' before `{}'",
+                              keyword.name(), value);
+            return Err(self.error(msg));
+        }
+        let mut host_port = None;
+        let event = match keyword {
+            Keyword::Machine  => {
+                let value = match value {
+                    Cow::Borrowed(v) if self.options.normalize_hosts =>
+                        Cow::Borrowed(crate::normalize_host(v)),
+                    Cow::Owned(v) if self.options.normalize_hosts =>
+                        Cow::Owned(crate::normalize_host(&v).to_string()),
+                    _ => value,
+                };
+                let value = match value {
+                    Cow::Borrowed(v) if self.options.host_ports => match machine_port(v) {
+                        Some((host, port)) => {
+                            host_port = Some(port);
+                            Cow::Borrowed(host)
+                        }
+                        None               => value,
+                    },
+                    Cow::Owned(ref v) if self.options.host_ports => match machine_port(v) {
+                        Some((host, port)) => {
+                            host_port = Some(port);
+                            Cow::Owned(host.to_string())
+                        }
+                        None               => value,
+                    },
+                    _ => value,
+                };
+                #[cfg(feature = "ipnet")]
+                {
+                    if self.options.allow_cidr {
+                        crate::cidr::check_host(&value)
+                            .map_err(|msg| self.error(msg))?;
+                    }
+                }
+                self.hosts += 1;
+                if self.hosts > self.options.max_hosts {
+                    let msg = format!("Too many machine entries (limit {})",
+                                      self.options.max_hosts);
+                    return Err(self.error(msg));
+                }
+                if self.options.strict && self.keyword_like(&value) {
+                    return Err(self.error("Missing host name for `machine'".to_string()));
+                }
+                self.open_block(Some(&value))?;
+                self.in_machine = true;
+                self.skipping = self.options.host_filter.as_ref()
+                    .is_some_and(|filter| !filter.accepts(&value));
+                if !self.skipping {
+                    self.check_duplicate(&value)?;
+                }
+                Event::Machine(value)
+            }
+            Keyword::Login    => Event::Login(value),
+            Keyword::Password => Event::Password(value),
+            Keyword::Account  => Event::Account(value),
+            Keyword::Port     => match value.parse() {
+                Ok(port) => Event::Port(port),
+                Err(_) if self.options.port_names && is_service_name(&value) => {
+                    let port = if self.options.resolve_port_names {
+                        service_port(&value)
+                    } else {
+                        None
+                    };
+                    match port {
+                        Some(port) => Event::Port(port),
+                        None       => Event::PortName(value),
+                    }
+                }
+                Err(_)   => {
+                    let msg = format!("Unable to parse port number `{}'",
+                                      value);
+                    return Err(self.error(msg));
+                }
+            },
+            Keyword::Protocol => Event::Protocol(value),
+        };
+        match self.block {
+            Some(ref mut block) if keyword != Keyword::Machine => {
+                if block.fields.contains(&keyword) {
+                    let msg = format!("Duplicate `{}' in the same entry", keyword.name());
+                    return Err(self.error(msg));
+                }
+                block.fields.push(keyword);
+            }
+            _ => (),
+        }
+        if !self.skipping {
+            match event {
+                Event::Login(ref v) if !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()) =>
+                    self.warn(WarningKind::NumericLogin, self.lnum),
+                Event::Password(ref v) if v.trim() != v =>
+                    self.warn(WarningKind::PasswordWhitespace, self.lnum),
+                _ => (),
+            }
+            self.sink.event(event, self.lnum);
+        }
+        if let Some(port) = host_port {
+            if let Some(ref mut block) = self.block {
+                block.fields.push(Keyword::Port);
+            }
+            if !self.skipping {
+                self.sink.event(Event::Port(port), self.lnum);
+            }
+        }
+        Ok(())
+    }
+
+    /// Start tracking the entry for `host`, or `default` if `None`, after
+    /// checking the one it ends.
+    fn open_block(&mut self, host: Option<&str>) -> Result<()> {
+        self.close_block()?;
+        if self.options.strict {
+            let host = host.map(str::to_string);
+            self.block = Some(Block { host, line: self.lnum, fields: Vec::new() });
+        }
+        Ok(())
+    }
+
+    /// Check the entry being ended in strict mode.
+    fn close_block(&mut self) -> Result<()> {
+        let block = match self.block.take() {
+            Some(block) => block,
+            None        => return Ok(()),
+        };
+        let after_default = match self.prev_block {
+            Some(Block { host: None, ref fields, .. }) => !fields.is_empty(),
+            _                                          => false,
+        };
+        if let (Some(ref host), true) = (&block.host, block.fields.is_empty()) {
+            let msg = if after_default {
+                format!("Machine `{}' has no fields; were they taken by \
+                         the `default' entry before it?", host)
+            } else {
+                format!("Machine `{}' has no fields", host)
+            };
+            return Err(Error::Parse { msg, line: block.line });
+        }
+        self.prev_block = Some(block);
+        Ok(())
+    }
+
+    /// Apply `options.on_duplicate_host` to a `machine` entry named `host`
+    /// that is about to be stored.
+    fn check_duplicate(&mut self, host: &str) -> Result<()> {
+        let policy = self.options.on_duplicate_host;
+        if policy == DuplicateHost::KeepAll || self.seen.insert(host.to_ascii_lowercase()) {
+            return Ok(());
+        }
+        match policy {
+            DuplicateHost::KeepFirst => self.skipping = true,
+            DuplicateHost::Error     => {
+                return Err(self.error(format!("Duplicate machine `{}'", host)));
+            }
+            DuplicateHost::KeepLast
+            | DuplicateHost::KeepAll
+            | DuplicateHost::Merge => (),
+        }
+        Ok(())
+    }
+
+    fn macro_text<'l>(&mut self, text: &'l str) -> Result<()>
+        where S: Sink<'l>
+    {
+        // Files from Windows end lines with CRLF, stored as a bare line
+        // break like the blank line ending the macro.
+        if let Some(rest) = text.strip_suffix("\r\n") {
+            if !rest.is_empty() {
+                self.macro_text(rest)?;
+            }
+            return self.macro_text("\n");
+        }
+        self.macro_length += text.len();
+        if self.macro_length > self.options.max_macro_length {
+            let msg = format!("Macro exceeds the maximum length of {} bytes",
+                              self.options.max_macro_length);
+            return Err(self.macro_error(msg));
+        }
+        if !text.is_empty() {
+            self.macro_newline = text.ends_with('\n');
+        }
+        if !text.trim().is_empty() {
+            self.macro_blank = false;
+        }
+        self.sink.event(Event::MacroText(text), self.lnum);
+        Ok(())
+    }
+
+    fn end_macro<'l>(&mut self)
+        where S: Sink<'l>
+    {
+        if self.macro_blank {
+            self.warn(WarningKind::EmptyMacro, self.macro_line);
+        }
+        self.state = State::Entry;
+        self.sink.event(Event::MacroEnd, self.lnum);
+    }
+}
+
+impl<'o> Parser<'o, Builder> {
+    pub fn finish_netrc(self) -> Result<Netrc> {
+        self.finish_netrc_with_warnings().map(|(netrc, _)| netrc)
+    }
+
+    pub fn finish_netrc_with_warnings(self) -> Result<(Netrc, Vec<Warning>)> {
+        self.finish_netrc_with_diagnostics().map(|(netrc, warnings, _)| (netrc, warnings))
+    }
+
+    /// Finish parsing, returning the warnings and, if `errors` was set,
+    /// the errors collected.
+    pub fn finish_netrc_with_diagnostics(mut self)
+                                         -> Result<(Netrc, Vec<Warning>, Vec<Error>)> {
+        let result = self.end();
+        self.recover(result)?;
+        let errors = self.errors.unwrap_or_default();
+        let options = self.options;
+        let mut netrc = self.sink.netrc;
+        netrc.lines = Some(self.lines);
+        if options.on_duplicate_host == DuplicateHost::KeepLast {
+            let mut seen = BTreeSet::new();
+            let mut keep: Vec<_> = netrc.hosts.iter().rev()
+                .map(|(name, _)| seen.insert(name.to_ascii_lowercase()))
+                .collect();
+            netrc.hosts.retain(|_| keep.pop().unwrap());
+        }
+        if options.on_duplicate_host == DuplicateHost::Merge {
+            let mut first: BTreeMap<String, usize> = BTreeMap::new();
+            let mut hosts: Vec<(String, Machine)> = Vec::new();
+            for (name, mach) in netrc.hosts.drain(..) {
+                match first.entry(name.to_ascii_lowercase()) {
+                    Entry::Occupied(e) => merge_machine(&mut hosts[*e.get()].1, mach),
+                    Entry::Vacant(e)   => {
+                        e.insert(hosts.len());
+                        hosts.push((name, mach));
+                    }
+                }
+            }
+            netrc.hosts = hosts;
+        }
+        if options.infer_port_from_protocol {
+            let hosts = netrc.hosts.iter_mut().map(|(_, m)| m);
+            for mach in hosts.chain(netrc.default.as_mut()) {
+                if mach.port.is_none() && mach.port_name.is_none() {
+                    mach.port = mach.protocol.as_ref().and_then(|p| default_port(p));
+                }
+            }
+        }
+        Ok((netrc, self.warnings, errors))
+    }
+}
+
+enum Current {
+    Nothing,
+    Default,
+    Host(usize),
+}
+
+/// A `Sink` building an owned `Netrc`.
+pub(crate) struct Builder {
+    pub netrc: Netrc,
+    current: Current,
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder { netrc: Default::default(), current: Current::Nothing }
+    }
+
+    fn current_machine(&mut self) -> &mut Machine {
+        match self.current {
+            Current::Default => self.netrc.default.as_mut().unwrap(),
+            Current::Host(n) => &mut self.netrc.hosts[n].1,
+            Current::Nothing => unreachable!("field outside of a machine"),
+        }
+    }
+}
+
+impl<'l> Sink<'l> for Builder {
+    fn event(&mut self, event: Event<'l>, line: usize) {
+        match event {
+            Event::Machine(name) => {
+                let mach = Machine { line: Some(line), ..Default::default() };
+                self.netrc.hosts.push((name.into_owned(), mach));
+                self.current = Current::Host(self.netrc.hosts.len() - 1);
+            }
+            Event::Default => {
+                self.netrc.default = Some(Machine { line: Some(line), ..Default::default() });
+                self.current = Current::Default;
+            }
+            Event::Login(v)    => self.current_machine().login = v.into_owned(),
+            Event::Password(v) => self.current_machine().password = Some(v.into_owned().into()),
+            Event::Account(v)  => self.current_machine().account = Some(v.into_owned().into()),
+            Event::Port(port)  => {
+                let m = self.current_machine();
+                m.port = Some(port);
+                m.port_name = None;
+            }
+            Event::PortName(v) => {
+                let m = self.current_machine();
+                m.port = None;
+                m.port_name = Some(v.into_owned());
+            }
+            Event::Protocol(v) => self.current_machine().protocol = Some(v.into_owned()),
+            Event::Extra(k, v) => {
+                self.current_machine().extras.push((k.into_owned(), v.into_owned()));
+            }
+            Event::MacroDef(name) => {
+                self.current = Current::Nothing;
+                self.netrc.macros.push(Macro {
+                    name: name.into_owned(),
+                    body: String::new(),
+                    lines: Some((line, line)),
+                });
+            }
+            Event::MacroText(text) => {
+                let mac = self.netrc.macros.last_mut().unwrap();
+                mac.body.push_str(text);
+            }
+            Event::MacroEnd => {
+                let mac = self.netrc.macros.last_mut().unwrap();
+                if let Some((_, ref mut end)) = mac.lines {
+                    *end = line;
+                }
+            }
+        }
+    }
+}
+
+/// Overwrite the fields of `mach` with those given in `later`.
+fn merge_machine(mach: &mut Machine, later: Machine) {
+    if !later.login.is_empty() {
+        mach.login = later.login;
+    }
+    if later.password.is_some() {
+        mach.password = later.password;
+    }
+    if later.account.is_some() {
+        mach.account = later.account;
+    }
+    if later.port.is_some() || later.port_name.is_some() {
+        mach.port = later.port;
+        mach.port_name = later.port_name;
+    }
+    if later.protocol.is_some() {
+        mach.protocol = later.protocol;
+    }
+    for (keyword, value) in later.extras {
+        match mach.extras.iter_mut().find(|(k, _)| *k == keyword) {
+            Some(extra) => extra.1 = value,
+            None        => mach.extras.push((keyword, value)),
+        }
+    }
+}
+
+/// Whether `word` starts an entry, and so is unlikely to be a value.
+fn is_keyword(word: &str) -> bool {
+    matches!(word, "machine" | "default" | "login" | "password" | "account"
+                   | "port" | "protocol" | "macdef")
+}
+
+/// Whether `name` looks like an IANA service name: letters, digits and
+/// hyphens, with at least one letter and no leading or trailing hyphen.
+fn is_service_name(name: &str) -> bool {
+    name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && name.chars().any(|c| c.is_ascii_alphabetic())
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+/// The well-known port of a protocol, for `infer_port_from_protocol`.
+pub(crate) fn default_port(protocol: &str) -> Option<u16> {
+    match &*protocol.to_ascii_lowercase() {
+        "http"  => Some(80),
+        "https" => Some(443),
+        "ftp"   => Some(21),
+        _       => None,
+    }
+}
+
+/// A `machine` name split into a host and a numeric port, for
+/// `host_ports`.  An IPv6 address needs brackets to have a port.
+fn machine_port(name: &str) -> Option<(&str, u16)> {
+    if !name.starts_with('[') && name.matches(':').count() != 1 {
+        return None;
+    }
+    match crate::split_host_port(name)? {
+        (host, Some(port)) => Some((host, port.parse().ok()?)),
+        (_, None)          => None,
+    }
+}
+
+/// The port of the service `name`, for `resolve_port_names`.
+fn service_port(name: &str) -> Option<u16> {
+    let name = name.to_ascii_lowercase();
+    let port = match &*name {
+        "ftp"                   => 21,
+        "ssh"                   => 22,
+        "telnet"                => 23,
+        "smtp"                  => 25,
+        "http"                  => 80,
+        "pop3"                  => 110,
+        "nntp"                  => 119,
+        "imap"                  => 143,
+        "ldap"                  => 389,
+        "https"                 => 443,
+        "smtps" | "submissions" => 465,
+        "nntps"                 => 563,
+        "submission"            => 587,
+        "ldaps"                 => 636,
+        "ftps"                  => 990,
+        "imaps"                 => 993,
+        "pop3s"                 => 995,
+        _                       => return system_service_port(&name),
+    };
+    Some(port)
+}
+
+#[cfg(not(all(unix, feature = "fs")))]
+fn system_service_port(_name: &str) -> Option<u16> {
+    None
+}
+
+pub(crate) struct Tokens<'a> {
+    buf: &'a str,
+    cur: usize,
+    /// Separate words on ASCII space, tab, CR and LF only.
+    ascii: bool,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn new(buf: &'a str, ascii: bool) -> Tokens<'a> {
+        Tokens { buf, cur: 0, ascii }
+    }
+
+    pub fn remaining(&self) -> &'a str {
+        &self.buf[self.cur..]
+    }
+
+    fn is_separator(&self, c: char) -> bool {
+        if self.ascii {
+            matches!(c, ' ' | '\t' | '\n' | '\r')
+        } else {
+            c.is_whitespace()
+        }
+    }
+
+    /// Return the next whitespace-delimited word.  With `quoting`, a word
+    /// starting with `"` instead extends to the matching quote, keeping any
+    /// whitespace in between.  See `unquote`.
+    pub fn next(&mut self, quoting: bool) -> Option<Cow<'a, str>> {
+        let rest = self.remaining().trim_start_matches(|c| self.is_separator(c));
+        self.cur = self.buf.len() - rest.len();
+        if rest.is_empty() {
+            return None;
+        }
+        if quoting && rest.starts_with('"') {
+            let (word, len) = unquote(&rest[1..]);
+            self.cur += len + 1;
+            return Some(word);
+        }
+        let len = rest.find(|c| self.is_separator(c)).unwrap_or(rest.len());
+        self.cur += len;
+        Some(Cow::Borrowed(&rest[..len]))
+    }
+}
+
+/// Read a quoted word from `s`, which follows its opening quote, up to the
+/// closing quote or else the end of the line.  A backslash escapes the
+/// character after it, and `\n` stands for a line break.  Returns the word
+/// and the length of `s` it takes up.
+fn unquote(s: &str) -> (Cow<'_, str>, usize) {
+    let line = s.trim_end_matches(['\n', '\r']);
+    let end = line.find(['"', '\\']).unwrap_or(line.len());
+    match line[end..].chars().next() {
+        Some('"') => return (Cow::Borrowed(&line[..end]), end + 1),
+        None      => return (Cow::Borrowed(line), s.len()),
+        _         => (),
+    }
+    let mut word = line[..end].to_string();
+    let mut chars = line[end..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"'  => return (Cow::Owned(word), end + i + 1),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => word.push('\n'),
+                Some((_, c))   => word.push(c),
+                None           => word.push(c),
+            },
+            _    => word.push(c),
+        }
+    }
+    (Cow::Owned(word), s.len())
+}
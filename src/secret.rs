@@ -0,0 +1,378 @@
+use alloc::string::{String, ToString};
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, Read};
+
+#[cfg(feature = "std")]
+use crate::Encoding;
+use crate::{Host, Machine, Netrc};
+
+/// A secret such as a password, kept out of logs: its `Debug` output is
+/// `[redacted]` and it has no `Display`, so the cleartext is only reachable
+/// through `expose`.  With the `secure` feature, the cleartext is
+/// overwritten with zeros when dropped.
+///
+/// With `secure`, so are the buffers input is read into by the parsers
+/// taking a byte stream and by `Netrc::from_file`, including the output of
+/// `gpg`.  Buffers inside a reader given to a parser are the caller's to
+/// wipe, as are those of the `tokio` feature's async parsers.  Lines are
+/// read into a buffer allocated up front for the longest line
+/// `ParseOptions.max_line_length` allows, but at most 1 MiB, so with a
+/// higher limit a longer line may leave copies of earlier ones behind as
+/// the buffer grows.
+#[derive(Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Secret(String);
+
+impl Secret {
+    /// The cleartext.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(s: String) -> Secret {
+        Secret(s)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(s: &str) -> Secret {
+        Secret(s.to_string())
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+/// Formats `T` with `{:?}` like its own `Debug`, but with the cleartext of
+/// its secrets.  See `Netrc::debug_unredacted`.
+struct Unredacted<'a, T: ?Sized>(&'a T);
+
+impl fmt::Debug for Unredacted<'_, Option<Secret>> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0.as_ref().map(Secret::expose), f)
+    }
+}
+
+impl fmt::Debug for Unredacted<'_, Machine> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mach = self.0;
+        f.debug_struct("Machine")
+            .field("login", &mach.login)
+            .field("password", &Unredacted(&mach.password))
+            .field("account", &Unredacted(&mach.account))
+            .field("port", &mach.port)
+            .field("port_name", &mach.port_name)
+            .field("protocol", &mach.protocol)
+            .field("extras", &mach.extras)
+            .field("line", &mach.line)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Unredacted<'_, [Host]> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(host, mach)| (host, Unredacted(mach))))
+            .finish()
+    }
+}
+
+impl fmt::Debug for Unredacted<'_, Netrc> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let netrc = self.0;
+        f.debug_struct("Netrc")
+            .field("hosts", &Unredacted(netrc.hosts.as_slice()))
+            .field("default", &netrc.default.as_ref().map(Unredacted))
+            .field("macros", &netrc.macros)
+            .field("lines", &netrc.lines)
+            .finish()
+    }
+}
+
+impl Machine {
+    /// Like `{:?}`, but showing the password and account instead of
+    /// `[redacted]`.  Meant for tests and debugging; do not log it.
+    pub fn debug_unredacted(&self) -> impl fmt::Debug + '_ {
+        Unredacted(self)
+    }
+}
+
+impl Netrc {
+    /// Like `{:?}`, but showing every password and account instead of
+    /// `[redacted]`.  Meant for tests and debugging; do not log it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    ///
+    /// let netrc = Netrc::parse_str("machine a.com password s3cret", &Default::default())
+    ///     .unwrap();
+    /// assert!(!format!("{:?}", netrc).contains("s3cret"));
+    /// assert!(format!("{:?}", netrc.debug_unredacted()).contains("Some(\"s3cret\")"));
+    /// ```
+    pub fn debug_unredacted(&self) -> impl fmt::Debug + '_ {
+        Unredacted(self)
+    }
+}
+
+#[cfg(feature = "secure")]
+impl Drop for Secret {
+    fn drop(&mut self) {
+        // Safety: NUL bytes are valid UTF-8.
+        wipe(unsafe { self.0.as_bytes_mut() });
+    }
+}
+
+/// Overwrite `bytes` with zeros, in a way the compiler may not optimize
+/// out for being dead before a free.
+#[cfg(feature = "secure")]
+pub(crate) fn wipe(bytes: &mut [u8]) {
+    for b in bytes {
+        unsafe { core::ptr::write_volatile(b, 0) };
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+/// The most allocated up front for reading lines.
+#[cfg(feature = "std")]
+const MAX_RESERVE: usize = 1 << 20;
+
+/// How much to allocate up front for reading a line of at most `max`
+/// bytes, `factor` times over for decoding, so that with the `secure`
+/// feature the buffer does not grow and leave copies behind.  Nothing
+/// without `secure`.
+#[cfg(feature = "std")]
+pub(crate) fn line_capacity(max: usize, factor: usize) -> usize {
+    if cfg!(feature = "secure") {
+        max.saturating_add(1).saturating_mul(factor).min(MAX_RESERVE)
+    } else {
+        0
+    }
+}
+
+/// The buffer lines of input are read into.  With the `secure` feature,
+/// it is wiped as `Secret` is when cleared or dropped, so passwords read
+/// from input do not linger in freed memory.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub(crate) struct LineBuffer(String);
+
+#[cfg(feature = "std")]
+impl LineBuffer {
+    /// A buffer for lines of at most `max` bytes of input decoded as
+    /// `encoding`, reserved up front as by `line_capacity`.
+    pub(crate) fn for_lines(max: usize, encoding: Encoding) -> LineBuffer {
+        // The most bytes of UTF-8 a byte of input may decode to.
+        let factor = match encoding {
+            Encoding::Utf8      => 1,
+            Encoding::Latin1    => 2,
+            Encoding::Utf8Lossy => 3,
+        };
+        LineBuffer(String::with_capacity(line_capacity(max, factor)))
+    }
+
+    pub(crate) fn clear(&mut self) {
+        // Safety: as for `Secret`.
+        #[cfg(feature = "secure")]
+        wipe(unsafe { self.0.as_bytes_mut() });
+        self.0.clear();
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for LineBuffer {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(feature = "std")]
+impl Deref for LineBuffer {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl DerefMut for LineBuffer {
+    fn deref_mut(&mut self) -> &mut String {
+        &mut self.0
+    }
+}
+
+/// Bytes of input, wiped as `Secret` is when dropped.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub(crate) struct SecretBytes(Vec<u8>);
+
+#[cfg(feature = "std")]
+impl SecretBytes {
+    /// Append everything `reader` holds.  With the `secure` feature, a full
+    /// buffer is copied to a larger one and wiped, instead of being grown
+    /// where the allocator may leave a copy behind.
+    #[cfg(feature = "gpg")]
+    pub(crate) fn read_from<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        let mut chunk = [0; 8192];
+        let result = loop {
+            let len = match reader.read(&mut chunk) {
+                Ok(0)                                             => break Ok(()),
+                Ok(len)                                           => len,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e)                                            => break Err(e),
+            };
+            #[cfg(feature = "secure")]
+            if self.0.len() + len > self.0.capacity() {
+                let mut grown = Vec::with_capacity((self.0.len() + len).max(2 * self.0.capacity()));
+                grown.extend_from_slice(&self.0);
+                wipe(&mut self.0);
+                self.0 = grown;
+            }
+            self.0.extend_from_slice(&chunk[..len]);
+        };
+        #[cfg(feature = "secure")]
+        wipe(&mut chunk);
+        result
+    }
+}
+
+#[cfg(feature = "std")]
+impl AsRef<[u8]> for SecretBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        #[cfg(feature = "secure")]
+        wipe(&mut self.0);
+    }
+}
+
+/// A `BufReader` whose buffer is wiped as `Secret` is when dropped.
+#[cfg(feature = "std")]
+pub(crate) struct SecretReader<R> {
+    inner: R,
+    buf: SecretBytes,
+    pos: usize,
+    filled: usize,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> SecretReader<R> {
+    pub(crate) fn new(inner: R) -> SecretReader<R> {
+        SecretReader { inner, buf: SecretBytes(vec![0; 8192]), pos: 0, filled: 0 }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for SecretReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let len = self.fill_buf()?.read(out)?;
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> BufRead for SecretReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.filled {
+            self.filled = self.inner.read(&mut self.buf.0)?;
+            self.pos = 0;
+        }
+        Ok(&self.buf.0[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.pos = (self.pos + len).min(self.filled);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::{Netrc, Secret};
+
+    #[test]
+    fn secret_redacted() {
+        let input = "machine example.com login test password s3cret account acct0";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let mach = &netrc.hosts[0].1;
+        let debug = format!("{:?} {:?}", netrc, mach);
+        assert!(!debug.contains("s3cret") && !debug.contains("acct0"), "{}", debug);
+        assert!(debug.contains("[redacted]"));
+        let borrowed = Netrc::parse_borrowed(input).unwrap();
+        let debug = format!("{:?} {:?}", borrowed, borrowed.hosts[0].1);
+        assert!(!debug.contains("s3cret") && !debug.contains("acct0"), "{}", debug);
+        assert!(debug.contains("password: Some([redacted])"), "{}", debug);
+        assert_eq!(mach.password.as_ref().unwrap().expose(), "s3cret");
+        assert_eq!(mach.account.as_ref().unwrap().expose(), "acct0");
+        assert_eq!(Secret::from("x"), Secret::from("x".to_string()));
+    }
+
+    #[test]
+    fn debug_unredacted() {
+        let input = "machine a.com login a password pa\ndefault account ad";
+        let netrc = Netrc::parse(input.as_bytes()).unwrap();
+        let debug = format!("{:?}", netrc.debug_unredacted());
+        assert_eq!(debug.replace("Some(\"pa\")", "Some([redacted])")
+                        .replace("Some(\"ad\")", "Some([redacted])"),
+                   format!("{:?}", netrc));
+        let debug = format!("{:?}", netrc.hosts[0].1.debug_unredacted());
+        assert!(debug.contains("password: Some(\"pa\")"), "{}", debug);
+    }
+
+    #[test]
+    fn secret_reader() {
+        use std::io::{BufRead, Read};
+
+        let text = "machine a.com password p\n".repeat(1000);
+        let mut reader = super::SecretReader::new(text.as_bytes());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "machine a.com password p\n");
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(line.len() + rest.len(), text.len());
+
+        #[cfg(feature = "gpg")]
+        {
+            let mut plain = super::SecretBytes::default();
+            plain.read_from(text.as_bytes()).unwrap();
+            assert_eq!(plain.as_ref(), text.as_bytes());
+        }
+    }
+
+    #[cfg(feature = "secure")]
+    #[test]
+    fn wipe_line_buffer() {
+        let mut line = super::LineBuffer::default();
+        line.push_str("password s3cret\n");
+        let ptr = line.as_ptr();
+        let len = line.len();
+        line.clear();
+        // Clearing keeps the allocation, so its bytes are still readable.
+        let bytes = unsafe { core::slice::from_raw_parts(ptr, len) };
+        assert!(bytes.iter().all(|&b| b == 0));
+
+        let line = super::LineBuffer::for_lines(16, crate::Encoding::Latin1);
+        assert!(line.capacity() >= 34);
+        let line = super::LineBuffer::for_lines(usize::MAX, crate::Encoding::Utf8);
+        assert_eq!(line.capacity(), super::MAX_RESERVE);
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::BufRead;
+use std::ops::ControlFlow;
+
+use crate::parser::{Builder, Event, Parser, Sink};
+use crate::secret::LineBuffer;
+use crate::{read_line, Machine, Netrc, Result};
+
+/// A `Sink` handing each `machine` entry to a callback once it is
+/// complete, instead of keeping it.
+struct Streamer<F> {
+    builder: Builder,
+    f: F,
+    stopped: bool,
+}
+
+impl<F: FnMut(&str, &Machine) -> ControlFlow<()>> Streamer<F> {
+    /// Pass on the `machine` entry being built, if any.
+    fn flush(&mut self) {
+        if let Some((name, mach)) = self.builder.netrc.hosts.pop() {
+            if !self.stopped {
+                self.stopped = (self.f)(&name, &mach).is_break();
+            }
+        }
+    }
+}
+
+impl<'l, F: FnMut(&str, &Machine) -> ControlFlow<()>> Sink<'l> for Streamer<F> {
+    fn event(&mut self, event: Event<'l>, line: usize) {
+        match event {
+            Event::Machine(_) | Event::Default    => self.flush(),
+            Event::MacroDef(_)                    => {
+                self.flush();
+                return;
+            }
+            Event::MacroText(_) | Event::MacroEnd => return,
+            _                                     => (),
+        }
+        self.builder.event(event, line);
+    }
+}
+
+impl Netrc {
+    /// Parse a byte stream, calling `f` with the name and fields of each
+    /// `machine` entry as soon as it is complete instead of building a
+    /// `Netrc`.  Parsing stops early when `f` returns `ControlFlow::Break`,
+    /// and the rest of the input is not read.  The `default` entry and
+    /// macros are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use netrc::Netrc;
+    /// use std::io::Cursor;
+    /// use std::ops::ControlFlow;
+    ///
+    /// let input: Cursor<&[u8]> =
+    ///   Cursor::new(b"machine a.com login a machine b.com login b");
+    /// let mut login = None;
+    /// Netrc::parse_with(input, |name, mach| {
+    ///     if name != "a.com" {
+    ///         return ControlFlow::Continue(());
+    ///     }
+    ///     login = Some(mach.login.clone());
+    ///     ControlFlow::Break(())
+    /// }).unwrap();
+    /// assert_eq!(login.unwrap(), "a");
+    /// ```
+    pub fn parse_with<A, F>(mut buf: A, f: F) -> Result<()>
+        where A: BufRead,
+              F: FnMut(&str, &Machine) -> ControlFlow<()>
+    {
+        let options = Default::default();
+        let sink = Streamer { builder: Builder::new(), f, stopped: false };
+        let mut parser = Parser::new(&options, sink);
+        let mut line = LineBuffer::for_lines(options.max_line_length, options.encoding);
+        while read_line(&mut buf, &mut line, options.max_line_length, options.encoding)? > 0 {
+            // Once stopped, the rest of the line does not matter.
+            let result = parser.feed_line(&line);
+            if parser.sink.stopped {
+                return Ok(());
+            }
+            result?;
+            line.clear();
+        }
+        parser.finish()?.flush();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Netrc;
+    use std::ops::ControlFlow;
+
+    #[test]
+    fn parse_with_stops_early() {
+        let input = "machine a.com login a
+                     machine b.com login b1
+                     machine b.com login b2 not netrc";
+        let mut seen = Vec::new();
+        Netrc::parse_with(input.as_bytes(), |name, mach| {
+            seen.push(format!("{}:{}", name, mach.login));
+            if name == "b.com" { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        }).unwrap();
+        assert_eq!(seen, ["a.com:a", "b.com:b1"]);
+    }
+
+    #[test]
+    fn parse_with_all() {
+        let input = "machine a.com login a
+                     macdef init
+                     bin
+
+                     machine b.com login b
+                     default login def";
+        let mut seen = Vec::new();
+        Netrc::parse_with(input.as_bytes(), |name, mach| {
+            seen.push(format!("{}:{}", name, mach.login));
+            ControlFlow::Continue(())
+        }).unwrap();
+        assert_eq!(seen, ["a.com:a", "b.com:b"]);
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+use crate::parser::{Event, Parser, Sink};
+use crate::secret::LineBuffer;
+use crate::{read_line, Error, ParseOptions, Result};
+
+/// What a `Token` holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A keyword or value.
+    Word,
+    /// The body of a macro, as stored in `Macro::body`.
+    MacroBody,
+}
+
+/// A piece of netrc input, with the line where it starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// The word, unquoted, or the macro body.
+    pub value: String,
+    /// The line of the word, or of the `macdef` keyword for a macro body.
+    pub line: usize,
+}
+
+/// A `Sink` keeping the words read and the macro bodies.
+#[derive(Default)]
+struct Recorder {
+    tokens: VecDeque<Token>,
+    body: Option<Token>,
+}
+
+impl<'l> Sink<'l> for Recorder {
+    fn event(&mut self, event: Event<'l>, line: usize) {
+        match event {
+            Event::MacroDef(_)     => {
+                let body = Token { kind: TokenKind::MacroBody, value: String::new(), line };
+                self.body = Some(body);
+            }
+            Event::MacroText(text) => if let Some(ref mut body) = self.body {
+                body.value.push_str(text);
+            },
+            Event::MacroEnd        => self.tokens.extend(self.body.take()),
+            _                      => (),
+        }
+    }
+
+    fn word(&mut self, word: &str, line: usize) {
+        self.tokens.push_back(Token { kind: TokenKind::Word, value: word.to_string(), line });
+    }
+}
+
+/// An iterator over the tokens of netrc input, read by the same parser as
+/// `Netrc::parse`, for tools that need the words rather than the entries.
+/// It yields the tokens up to a syntax error, which it then yields.
+///
+/// # Examples
+///
+/// ```
+/// use netrc::Tokenizer;
+///
+/// let input: &[u8] = b"machine example.com\nlogin foo";
+/// let words: Vec<_> = Tokenizer::new(input)
+///     .map(|token| token.map(|t| (t.value, t.line)))
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(words[2], ("login".to_string(), 2));
+/// ```
+pub struct Tokenizer<A> {
+    buf: A,
+    /// `None` once the input is read.
+    parser: Option<Parser<'static, Recorder>>,
+    line: LineBuffer,
+    pending: VecDeque<Token>,
+    error: Option<Error>,
+}
+
+impl<A: BufRead> Tokenizer<A> {
+    /// Split `buf` as parsing with the default `ParseOptions` would.
+    pub fn new(buf: A) -> Tokenizer<A> {
+        Tokenizer::with_options(buf, &Default::default())
+    }
+
+    /// Split words as parsing with `options` would.  Macro bodies are
+    /// found as when parsing, and the same input is rejected.
+    pub fn with_options(buf: A, options: &ParseOptions) -> Tokenizer<A> {
+        let line = LineBuffer::for_lines(options.max_line_length, options.encoding);
+        Tokenizer {
+            buf,
+            parser: Some(Parser::with_owned_options(options.clone(), Recorder::default())),
+            line,
+            pending: VecDeque::new(),
+            error: None,
+        }
+    }
+
+    /// Read the next line, queueing its tokens in `pending`, or finish
+    /// parsing at end of input.
+    fn read(&mut self) -> Result<()> {
+        let parser = match self.parser {
+            Some(ref mut parser) => parser,
+            None                 => return Ok(()),
+        };
+        let max = parser.options.max_line_length;
+        let encoding = parser.options.encoding;
+        self.line.clear();
+        if read_line(&mut self.buf, &mut self.line, max, encoding)? == 0 {
+            let recorder = self.parser.take().expect("parser").finish()?;
+            self.pending.extend(recorder.tokens);
+            return Ok(());
+        }
+        let result = parser.feed_line(&self.line);
+        self.pending.extend(parser.sink.tokens.drain(..));
+        result
+    }
+}
+
+impl<A: BufRead> Iterator for Tokenizer<A> {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Result<Token>> {
+        loop {
+            if let Some(token) = self.pending.pop_front() {
+                return Some(Ok(token));
+            }
+            if let Some(e) = self.error.take() {
+                return Some(Err(e));
+            }
+            self.parser.as_ref()?;
+            if let Err(e) = self.read() {
+                self.parser = None;
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Token, TokenKind, Tokenizer};
+
+    #[test]
+    fn tokenize_lines() {
+        let input = "machine example.com
+                       login foo password bar
+                     macdef init
+                       bin
+
+                     default login def";
+        let tokens: Vec<Token> = Tokenizer::new(input.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let words: Vec<_> = tokens.iter()
+            .filter(|t| t.kind == TokenKind::Word)
+            .map(|t| (t.value.as_str(), t.line))
+            .collect();
+        assert_eq!(words, [("machine", 1), ("example.com", 1), ("login", 2), ("foo", 2),
+                           ("password", 2), ("bar", 2), ("macdef", 3), ("init", 3),
+                           ("default", 6), ("login", 6), ("def", 6)]);
+        let body = tokens.iter().find(|t| t.kind == TokenKind::MacroBody).unwrap();
+        assert_eq!(body.line, 3);
+        assert_eq!(body.value.trim(), "bin");
+    }
+
+    #[test]
+    fn tokenize_error() {
+        let options = crate::ParseOptions { max_line_length: 8, ..Default::default() };
+        let mut tokens = Tokenizer::with_options("login foo\n".as_bytes(), &options);
+        assert!(tokens.next().unwrap().is_err());
+        assert!(tokens.next().is_none());
+
+        let mut tokens = Tokenizer::new("machine a.com\nlogin".as_bytes());
+        assert_eq!(tokens.by_ref().take(3).map(|t| t.unwrap().value).collect::<Vec<_>>(),
+                   ["machine", "a.com", "login"]);
+        assert!(tokens.next().unwrap().is_err());
+        assert!(tokens.next().is_none());
+    }
+
+    #[test]
+    fn tokenize_case_insensitive_macdef() {
+        let input = "MacDef init\nmachine a.com\n\ndefault login a";
+        let options = crate::ParseOptions {
+            case_insensitive_keywords: true,
+            ..Default::default()
+        };
+        let kinds: Vec<_> = Tokenizer::with_options(input.as_bytes(), &options)
+            .map(|t| t.unwrap().kind)
+            .collect();
+        assert_eq!(kinds, [TokenKind::Word, TokenKind::Word, TokenKind::MacroBody,
+                           TokenKind::Word, TokenKind::Word, TokenKind::Word]);
+    }
+
+    #[test]
+    fn tokenize_assignments() {
+        let input = "macdef=init\ncd /pub\n\nmachine=a.com login=a";
+        let options = crate::ParseOptions { assignments: true, ..Default::default() };
+        let tokens: Vec<_> = Tokenizer::with_options(input.as_bytes(), &options)
+            .map(|t| t.map(|t| (t.kind, t.value)))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let word = |w: &str| (TokenKind::Word, w.to_string());
+        assert_eq!(tokens, [word("macdef"), word("init"),
+                            (TokenKind::MacroBody, "\ncd /pub\n\n".to_string()),
+                            word("machine"), word("a.com"), word("login"), word("a")]);
+    }
+
+    #[test]
+    fn tokenize_comments() {
+        let input = "# macdef x\nmachine a.com # note\npassword #secret\n\nlogin a";
+        let options = crate::ParseOptions { comments: true, ..Default::default() };
+        let tokens: Vec<Token> = Tokenizer::with_options(input.as_bytes(), &options)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(tokens.iter().all(|t| t.kind == TokenKind::Word));
+        let words: Vec<_> = tokens.iter().map(|t| t.value.as_str()).collect();
+        assert_eq!(words, ["machine", "a.com", "password", "#secret", "login", "a"]);
+        let netrc = crate::Netrc::parse_with_options(input.as_bytes(), &options).unwrap();
+        assert_eq!(netrc.hosts[0].1.password.as_ref().unwrap().expose(), "#secret");
+        assert!(netrc.macros.is_empty());
+    }
+}